@@ -1,4 +1,5 @@
 use std::ops::Range;
+use std::time::Duration;
 
 use bevy::{prelude::*, sprite::TextureAtlasSprite, utils::HashMap};
 use serde::de::SeqAccess;
@@ -6,11 +7,31 @@ use serde::Deserializer;
 
 use crate::GameState;
 
+pub use sheet::AnimationSheet;
+use sheet::AnimationSheetLoader;
+pub use state_machine::{AnimationContext, StateMachine};
+
+mod sheet;
+mod state_machine;
+
 pub struct AnimationPlugin;
 
+/// Fastest a clip can be sped up by its speed reference
+const MAX_PLAYBACK_SPEED: f32 = 10.;
+
 impl Plugin for AnimationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Last, animation_cycling.run_if(in_state(GameState::Playing)));
+        app.init_asset::<AnimationSheet>()
+            .init_asset_loader::<AnimationSheetLoader>()
+            .add_event::<AnimationFinished>()
+            .add_event::<AnimationFrameEvent>()
+            .add_systems(Update, reload_animation_sheets)
+            .add_systems(
+                Last,
+                (animation_cycling, despawn_on_animation_end)
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
 
@@ -19,8 +40,56 @@ impl Plugin for AnimationPlugin {
 pub struct Clip {
     #[serde(deserialize_with = "deserialize_range_from_array")]
     pub frames: Range<usize>,
+    /// Filled in from `mode` or `repeat` when the sheet is loaded
+    #[serde(skip)]
+    pub mode: PlaybackMode,
+    /// The `mode` as written in the descriptor
+    #[serde(default, rename = "mode")]
+    mode_setting: Option<PlaybackMode>,
+    /// Shorthand for `mode: loop` or `mode: once`
+    #[serde(default)]
+    repeat: Option<bool>,
+    /// Play the frames from last to first
+    #[serde(default)]
+    pub reverse: bool,
+    /// Seconds per frame, overriding the frame duration of the sheet
+    #[serde(default)]
+    pub frame_duration: Option<f32>,
+    /// Seconds for each frame of the clip in order, overriding `frame_duration`
+    /// for as many frames as are listed
+    #[serde(default)]
+    pub frame_durations: Vec<f32>,
+    /// Horizontal speed at which the clip plays at normal speed; when set,
+    /// the state machine speeds the clip up or down to match the movement speed
+    #[serde(default)]
+    pub speed_reference: Option<f32>,
+    /// Named events and the sprite sheet frames they fire on, e.g. `footstep: [13, 16]`
     #[serde(default)]
-    pub repeat: bool,
+    pub events: HashMap<String, Vec<usize>>,
+}
+
+impl Clip {
+    /// Number of frames in the clip
+    pub fn len(&self) -> usize {
+        self.frames.end.saturating_sub(self.frames.start) + 1
+    }
+
+    pub fn is_repeating(&self) -> bool {
+        self.mode != PlaybackMode::Once
+    }
+}
+
+/// What happens when a clip reaches its last frame
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackMode {
+    /// Stop on the last frame
+    #[default]
+    Once,
+    /// Start over from the first frame
+    Loop,
+    /// Play back to the first frame, then forwards again
+    PingPong,
 }
 
 fn deserialize_range_from_array<'de, D>(de: D) -> Result<Range<usize>, D::Error>
@@ -67,10 +136,23 @@ impl<'de> serde::de::Visitor<'de> for RangeVisitor {
 #[derive(Component)]
 pub struct Animation {
     pub animations: HashMap<String, Clip>,
+    /// The frame being shown, counted from the first frame of the current clip
     pub current_frame: usize,
     pub current_animation: Option<String>,
     pub timer: Timer,
     pub played_once: bool,
+    /// Seconds per frame for clips that don't set their own
+    pub frame_duration: f32,
+    /// Playback speed multiplier
+    pub speed: f32,
+    pub state_machine: StateMachine,
+    /// The state machine state being played, if the current clip was picked by the state machine
+    pub current_state: Option<String>,
+    /// Whether the current clip is stepping from its last frame towards its first
+    backwards: bool,
+    /// Whether the current frame has just been shown for the first time,
+    /// so its frame events are due
+    frame_entered: bool,
 }
 
 impl Animation {
@@ -79,58 +161,210 @@ impl Animation {
             animations,
             current_frame: 0,
             current_animation: None,
-            timer: Timer::from_seconds(frame_duration, TimerMode::Repeating),
+            timer: Timer::from_seconds(frame_duration, TimerMode::Once),
             played_once: false,
+            frame_duration,
+            speed: 1.,
+            state_machine: StateMachine::default(),
+            current_state: None,
+            backwards: false,
+            frame_entered: false,
         }
     }
 
+    pub fn with_state_machine(mut self, state_machine: StateMachine) -> Self {
+        self.state_machine = state_machine;
+        self
+    }
+
     /// Start playing a new animation
-    pub fn play(&mut self, name: &str, repeating: bool) {
+    pub fn play(&mut self, name: &str) {
         self.current_animation = Some(name.to_owned());
-        self.current_frame = 0;
-        self.timer.reset();
-        self.timer.unpause();
-        self.timer.set_mode(if repeating {
-            TimerMode::Repeating
-        } else {
-            TimerMode::Once
-        });
+        self.backwards = self.current_clip().is_some_and(|clip| clip.reverse);
+        self.current_frame = match self.current_clip() {
+            Some(clip) if clip.reverse => clip.len() - 1,
+            _ => 0,
+        };
+        self.speed = 1.;
         self.played_once = false;
+        self.current_state = None;
+        self.frame_entered = true;
+        self.restart_frame_timer();
     }
 
-    pub fn is_finished(&self) -> bool {
-        self.played_once
-    }
+    /// Let the state machine switch to the clip that fits what the entity is doing
+    pub fn update_state(&mut self, context: &AnimationContext) {
+        let Some(next) = self.state_machine.next_state(
+            self.current_state.as_deref(),
+            self.is_finished(),
+            context,
+        ) else {
+            return;
+        };
 
-    pub fn is_repeating(&self) -> bool {
-        if let Some(animation) = &self.current_animation {
-            if let Some(clip) = self.animations.get(animation) {
-                return clip.repeat;
+        if self.current_state.as_deref() != Some(next.name.as_str()) {
+            let name = next.name.clone();
+            let clip = next.clip().to_owned();
+            let continue_frame = self
+                .current_state
+                .as_ref()
+                .is_some_and(|current| next.continue_from.contains(current));
+            let frame = self.current_frame;
+
+            self.play(&clip);
+            if continue_frame {
+                if let Some(clip) = self.current_clip() {
+                    self.current_frame = frame.min(clip.len() - 1);
+                    // The frame was already entered in the previous clip
+                    self.frame_entered = self.current_frame != frame;
+                }
             }
+            self.current_state = Some(name);
+        }
+
+        if let Some(speed_reference) = self.current_clip().and_then(|clip| clip.speed_reference) {
+            self.speed = context.velocity.x.abs() / speed_reference;
         }
+    }
 
-        false
+    /// Replace the clip table while keeping the current playback state,
+    /// e.g. after the animation sheet has been hot-reloaded
+    pub fn set_clips(&mut self, frame_duration: f32, animations: HashMap<String, Clip>) {
+        self.animations = animations;
+        self.frame_duration = frame_duration;
+
+        if let Some(clip) = self.current_clip() {
+            self.current_frame = self.current_frame.min(clip.len() - 1);
+        }
+        self.timer
+            .set_duration(Duration::from_secs_f32(self.current_frame_duration()));
     }
 
-    pub fn is_last_frame(&self) -> bool {
-        if let Some(indices) = self.get_current_indices() {
-            if let Some(index) = self.get_current_index() {
-                return index >= indices.end;
+    /// Advances the current clip by `delta`, scaled by the playback speed.
+    /// Returns true when the clip has played to its end; repeating clips then start over.
+    pub fn tick(&mut self, delta: Duration) -> bool {
+        if self.is_finished() && !self.is_repeating() {
+            return false;
+        }
+
+        let speed = if self.speed.is_nan() {
+            0.
+        } else {
+            self.speed.clamp(0., MAX_PLAYBACK_SPEED)
+        };
+        if !self.timer.tick(delta.mul_f32(speed)).finished() {
+            return false;
+        }
+
+        let previous_frame = self.current_frame;
+        let finished = self.step();
+        // A one-frame loop enters its only frame again on every wrap-around
+        self.frame_entered =
+            self.current_frame != previous_frame || (finished && self.is_repeating());
+        self.restart_frame_timer();
+        if finished {
+            self.played_once = true;
+        }
+
+        finished
+    }
+
+    /// Moves on to the next frame, returning true if the clip has reached its end
+    fn step(&mut self) -> bool {
+        let Some(clip) = self.current_clip() else {
+            return false;
+        };
+        let last_frame = clip.len() - 1;
+        let (mode, reverse) = (clip.mode, clip.reverse);
+
+        let at_end = if self.backwards {
+            self.current_frame == 0
+        } else {
+            self.current_frame >= last_frame
+        };
+        if !at_end {
+            if self.backwards {
+                self.current_frame -= 1;
+            } else {
+                self.current_frame += 1;
             }
+            return false;
         }
 
-        false
+        match mode {
+            PlaybackMode::Once => true,
+            PlaybackMode::Loop => {
+                self.current_frame = if reverse { last_frame } else { 0 };
+                true
+            }
+            PlaybackMode::PingPong => {
+                // A full cycle is there and back again
+                let finished = self.backwards != reverse;
+                self.backwards = !self.backwards;
+                if last_frame > 0 {
+                    if self.backwards {
+                        self.current_frame -= 1;
+                    } else {
+                        self.current_frame += 1;
+                    }
+                }
+                finished
+            }
+        }
+    }
+
+    fn restart_frame_timer(&mut self) {
+        let duration = self.current_frame_duration();
+        self.timer.set_duration(Duration::from_secs_f32(duration));
+        self.timer.reset();
+        self.timer.unpause();
+    }
+
+    /// Seconds the current frame is shown for at normal speed
+    fn current_frame_duration(&self) -> f32 {
+        let Some(clip) = self.current_clip() else {
+            return self.frame_duration;
+        };
+
+        clip.frame_durations
+            .get(self.current_frame)
+            .copied()
+            .or(clip.frame_duration)
+            .unwrap_or(self.frame_duration)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.played_once
+    }
+
+    pub fn is_repeating(&self) -> bool {
+        self.current_clip().is_some_and(Clip::is_repeating)
+    }
+
+    pub fn current_clip(&self) -> Option<&Clip> {
+        self.current_animation
+            .as_ref()
+            .and_then(|animation| self.animations.get(animation))
     }
 
     pub fn get_current_indices(&self) -> Option<&Range<usize>> {
-        if let Some(animation) = &self.current_animation {
-            return match self.animations.get(animation) {
-                Some(clip) => Some(&clip.frames),
-                None => None,
-            };
+        self.current_clip().map(|clip| &clip.frames)
+    }
+
+    /// Names of the events on the current frame, if the frame was entered since this was last called
+    fn take_frame_events(&mut self) -> Vec<String> {
+        if !std::mem::take(&mut self.frame_entered) {
+            return Vec::new();
         }
+        let (Some(clip), Some(index)) = (self.current_clip(), self.get_current_index()) else {
+            return Vec::new();
+        };
 
-        None
+        clip.events
+            .iter()
+            .filter(|(_, frames)| frames.contains(&index))
+            .map(|(name, _)| name.clone())
+            .collect()
     }
 
     pub fn get_current_index(&self) -> Option<usize> {
@@ -142,31 +376,182 @@ impl Animation {
     }
 }
 
-fn animation_cycling(mut query: Query<(&mut TextureAtlasSprite, &mut Animation)>, time: Res<Time>) {
-    //TODO: Add a tick method on Animation
-    for (mut texture_atlas_sprite, mut animation) in query.iter_mut() {
-        if animation.is_finished() && !animation.is_repeating() {
-            continue;
-        }
+/// Sent when a clip has shown its last frame; repeating clips send it once per loop
+#[derive(Event, Clone, Debug)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+    /// Name of the clip that finished
+    pub animation: String,
+}
 
-        animation.timer.tick(time.delta());
+/// Sent when a clip shows a frame it has declared an event on
+#[derive(Event, Clone, Debug)]
+pub struct AnimationFrameEvent {
+    pub entity: Entity,
+    /// Name of the event, as declared in the clip
+    pub name: String,
+}
 
-        if animation.timer.finished() {
-            animation.timer.reset();
+/// Despawns the entity as soon as its current non-repeating clip has finished
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct DespawnOnAnimationEnd;
 
-            if animation.is_last_frame() {
-                animation.played_once = true; // Check if animation player here because we need to wait the last frame
+fn animation_cycling(
+    mut query: Query<(Entity, &mut TextureAtlasSprite, &mut Animation)>,
+    mut finished_events: EventWriter<AnimationFinished>,
+    mut frame_events: EventWriter<AnimationFrameEvent>,
+    time: Res<Time>,
+) {
+    for (entity, mut texture_atlas_sprite, mut animation) in query.iter_mut() {
+        // Events on the first frame of a clip are due as soon as it starts playing
+        for name in animation.take_frame_events() {
+            frame_events.send(AnimationFrameEvent { entity, name });
+        }
 
-                if animation.is_repeating() {
-                    animation.current_frame = 0;
-                }
-            } else {
-                animation.current_frame += 1;
+        if animation.tick(time.delta()) {
+            if let Some(name) = &animation.current_animation {
+                finished_events.send(AnimationFinished {
+                    entity,
+                    animation: name.clone(),
+                });
             }
         }
 
+        for name in animation.take_frame_events() {
+            frame_events.send(AnimationFrameEvent { entity, name });
+        }
+
         if let Some(index) = animation.get_current_index() {
             texture_atlas_sprite.index = index;
         }
     }
 }
+
+/// Swap in the new clips for every entity animated from a sheet that has been modified on disk
+fn reload_animation_sheets(
+    mut events: EventReader<AssetEvent<AnimationSheet>>,
+    mut query: Query<(&Handle<AnimationSheet>, &mut Animation)>,
+    animation_sheets: Res<Assets<AnimationSheet>>,
+) {
+    for event in events.read() {
+        if let AssetEvent::Modified { id } = event {
+            let Some(sheet) = animation_sheets.get(*id) else {
+                continue;
+            };
+
+            for (handle, mut animation) in &mut query {
+                if handle.id() == *id {
+                    animation.set_clips(sheet.frame_duration, sheet.clips.clone());
+                    animation.state_machine = sheet.state_machine.clone();
+                }
+            }
+        }
+    }
+}
+
+fn despawn_on_animation_end(
+    mut commands: Commands,
+    mut finished_events: EventReader<AnimationFinished>,
+    query: Query<&Animation, With<DespawnOnAnimationEnd>>,
+) {
+    for finished in finished_events.read() {
+        if let Ok(animation) = query.get(finished.entity) {
+            if !animation.is_repeating() {
+                commands.entity(finished.entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(mode: PlaybackMode, reverse: bool) -> Animation {
+        let clip = Clip {
+            frames: 10..13,
+            mode,
+            mode_setting: None,
+            repeat: None,
+            reverse,
+            frame_duration: None,
+            frame_durations: Vec::new(),
+            speed_reference: None,
+            events: HashMap::default(),
+        };
+        let mut animation = Animation::new(0.1, HashMap::from([(String::from("clip"), clip)]));
+        animation.play("clip");
+        animation
+    }
+
+    /// The frame shown and whether the clip finished, for each of `count` steps
+    fn steps(animation: &mut Animation, count: usize) -> Vec<(usize, bool)> {
+        (0..count)
+            .map(|_| {
+                let finished = animation.step();
+                (animation.current_frame, finished)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_starts_over_after_the_last_frame() {
+        let mut animation = animation(PlaybackMode::Loop, false);
+
+        assert_eq!(animation.current_frame, 0);
+        assert_eq!(
+            steps(&mut animation, 5),
+            [(1, false), (2, false), (3, false), (0, true), (1, false)]
+        );
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut animation = animation(PlaybackMode::Once, false);
+
+        assert_eq!(
+            steps(&mut animation, 5),
+            [(1, false), (2, false), (3, false), (3, true), (3, true)]
+        );
+    }
+
+    #[test]
+    fn ping_pong_finishes_after_going_there_and_back() {
+        let mut animation = animation(PlaybackMode::PingPong, false);
+
+        assert_eq!(
+            steps(&mut animation, 7),
+            [
+                (1, false),
+                (2, false),
+                (3, false),
+                (2, false),
+                (1, false),
+                (0, false),
+                (1, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse_plays_from_the_last_frame_to_the_first() {
+        let mut animation = animation(PlaybackMode::Loop, true);
+
+        assert_eq!(animation.current_frame, 3);
+        assert_eq!(animation.get_current_index(), Some(13));
+        assert_eq!(
+            steps(&mut animation, 5),
+            [(2, false), (1, false), (0, false), (3, true), (2, false)]
+        );
+    }
+
+    #[test]
+    fn reverse_once_stops_on_the_first_frame() {
+        let mut animation = animation(PlaybackMode::Once, true);
+
+        assert_eq!(
+            steps(&mut animation, 4),
+            [(2, false), (1, false), (0, false), (0, true)]
+        );
+    }
+}
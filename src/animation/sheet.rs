@@ -0,0 +1,126 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+
+use super::{Animation, Clip, PlaybackMode, StateMachine};
+
+/// A sprite sheet together with the animation clips it contains,
+/// loaded from a `.yml` descriptor such as `textures/cyborg.yml`
+#[derive(Asset, TypePath, Debug)]
+pub struct AnimationSheet {
+    pub atlas: Handle<TextureAtlas>,
+    pub frame_duration: f32,
+    pub clips: HashMap<String, Clip>,
+    pub state_machine: StateMachine,
+}
+
+impl AnimationSheet {
+    /// Creates a new `Animation` component playing clips from this sheet
+    pub fn animation(&self) -> Animation {
+        Animation::new(self.frame_duration, self.clips.clone())
+            .with_state_machine(self.state_machine.clone())
+    }
+}
+
+/// The on-disk format of an animation descriptor
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimationSheetDescriptor {
+    /// Path to the sprite sheet image, relative to the descriptor
+    image: String,
+    tile_size: [f32; 2],
+    columns: usize,
+    rows: usize,
+    animation_frame_duration: f32,
+    animations: HashMap<String, Clip>,
+    /// Which clip to play when, in order of priority
+    #[serde(default)]
+    states: StateMachine,
+}
+
+#[derive(Default)]
+pub struct AnimationSheetLoader;
+
+impl AssetLoader for AnimationSheetLoader {
+    type Asset = AnimationSheet;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let mut descriptor: AnimationSheetDescriptor = serde_yaml::from_slice(&bytes)?;
+
+            if !is_positive(descriptor.animation_frame_duration) {
+                anyhow::bail!("Animation frame duration must be a positive number of seconds");
+            }
+            let frame_count = descriptor.columns * descriptor.rows;
+            for (name, clip) in &mut descriptor.animations {
+                clip.mode = match (clip.mode_setting.take(), clip.repeat.take()) {
+                    (Some(_), Some(_)) => {
+                        anyhow::bail!("Animation {name} sets both mode and repeat")
+                    }
+                    (Some(mode), None) => mode,
+                    (None, Some(true)) => PlaybackMode::Loop,
+                    (None, Some(false) | None) => PlaybackMode::Once,
+                };
+                let (start, end) = (clip.frames.start, clip.frames.end);
+                if start > end {
+                    anyhow::bail!("Animation {name} has frames [{start}, {end}] out of order");
+                }
+                if end >= frame_count {
+                    anyhow::bail!("Animation {name} uses frame {end}, the sheet has {frame_count}");
+                }
+                let frame_durations = clip.frame_duration.iter().chain(&clip.frame_durations);
+                if !frame_durations.copied().all(is_positive) {
+                    anyhow::bail!("Animation {name} has a frame duration that isn't positive");
+                }
+                if clip
+                    .speed_reference
+                    .is_some_and(|speed| !is_positive(speed))
+                {
+                    anyhow::bail!("Animation {name} has a speed reference that isn't positive");
+                }
+            }
+
+            let image_path = match load_context.path().parent() {
+                Some(directory) => directory.join(&descriptor.image),
+                None => descriptor.image.into(),
+            };
+            let image = load_context.load(image_path);
+
+            let atlas = TextureAtlas::from_grid(
+                image,
+                Vec2::from(descriptor.tile_size),
+                descriptor.columns,
+                descriptor.rows,
+                None,
+                None,
+            );
+
+            Ok(AnimationSheet {
+                atlas: load_context.add_labeled_asset(String::from("atlas"), atlas),
+                frame_duration: descriptor.animation_frame_duration,
+                clips: descriptor.animations,
+                state_machine: descriptor.states,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["yml"]
+    }
+}
+
+/// Whether `value` is a usable duration or speed, i.e. finite and above zero
+fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.
+}
@@ -0,0 +1,255 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::movement::WallMove;
+use crate::PlayerAction;
+
+/// Picks which clip to play from what an entity is doing, declared under `states`
+/// in an animation descriptor. States are listed by priority: the first state whose
+/// conditions hold is the one that plays.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct StateMachine {
+    pub states: Vec<AnimationState>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationState {
+    pub name: String,
+    /// The clip to play, the clip with the same name as the state if left out
+    #[serde(default)]
+    pub clip: Option<String>,
+    #[serde(default)]
+    pub when: Conditions,
+    /// Once entered, only states listed before this one can interrupt it until its clip has finished
+    #[serde(default)]
+    pub until_finished: bool,
+    /// States to carry the current frame over from instead of restarting the clip,
+    /// so e.g. walking and running can blend into each other mid-stride
+    #[serde(default)]
+    pub continue_from: Vec<String>,
+}
+
+impl AnimationState {
+    pub fn clip(&self) -> &str {
+        self.clip.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Everything that must hold for a state to be entered; conditions that are left out always hold
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+    pub grounded: Option<bool>,
+    /// Minimum horizontal speed
+    pub min_speed: Option<f32>,
+    /// Maximum horizontal speed
+    pub max_speed: Option<f32>,
+    /// Whether moving upwards
+    pub rising: Option<bool>,
+    /// What is being done against a wall
+    pub wall: Option<WallMove>,
+    /// Actions that must be held
+    #[serde(default)]
+    pub pressed: Vec<PlayerAction>,
+    /// States this state can be entered from
+    #[serde(default)]
+    pub from: Vec<String>,
+}
+
+/// What an animated entity is doing, as seen by its state machine
+pub struct AnimationContext<'a> {
+    pub grounded: bool,
+    pub velocity: Vec2,
+    pub wall: Option<WallMove>,
+    pub actions: Option<&'a ActionState<PlayerAction>>,
+}
+
+impl Conditions {
+    fn hold(&self, context: &AnimationContext, current: Option<&str>) -> bool {
+        let speed = context.velocity.x.abs();
+
+        self.grounded
+            .is_none_or(|grounded| grounded == context.grounded)
+            && self.min_speed.is_none_or(|min_speed| speed >= min_speed)
+            && self.max_speed.is_none_or(|max_speed| speed <= max_speed)
+            && self
+                .rising
+                .is_none_or(|rising| rising == (context.velocity.y > 0.))
+            && self.wall.is_none_or(|wall| context.wall == Some(wall))
+            && self.pressed.iter().all(|&action| {
+                context
+                    .actions
+                    .is_some_and(|action_state| action_state.pressed(action))
+            })
+            && (self.from.is_empty()
+                || current.is_some_and(|current| self.from.iter().any(|from| from == current)))
+    }
+}
+
+impl StateMachine {
+    /// The state that should be playing, given the current state and whether its clip has finished
+    pub fn next_state(
+        &self,
+        current: Option<&str>,
+        finished: bool,
+        context: &AnimationContext,
+    ) -> Option<&AnimationState> {
+        let locked = current.filter(|_| !finished).and_then(|current| {
+            self.states
+                .iter()
+                .position(|state| state.name == current && state.until_finished)
+        });
+        let candidates = match locked {
+            Some(index) => &self.states[..index],
+            None => &self.states[..],
+        };
+
+        candidates
+            .iter()
+            .find(|state| state.when.hold(context, current))
+            .or_else(|| locked.map(|index| &self.states[index]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATES: &str = "
+- name: land
+  when:
+    grounded: true
+    from: [jump]
+- name: slide
+  when:
+    wall: sliding
+- name: jump
+  when:
+    grounded: false
+    rising: true
+- name: run
+  when:
+    grounded: true
+    min_speed: 10
+- name: idle
+";
+
+    fn context(
+        grounded: bool,
+        velocity: Vec2,
+        wall: Option<WallMove>,
+    ) -> AnimationContext<'static> {
+        AnimationContext {
+            grounded,
+            velocity,
+            wall,
+            actions: None,
+        }
+    }
+
+    fn next<'a>(
+        state_machine: &'a StateMachine,
+        current: Option<&str>,
+        finished: bool,
+        context: &AnimationContext,
+    ) -> Option<&'a str> {
+        state_machine
+            .next_state(current, finished, context)
+            .map(|state| state.name.as_str())
+    }
+
+    #[test]
+    fn picks_the_first_state_whose_conditions_hold() {
+        let state_machine: StateMachine = serde_yaml::from_str(STATES).unwrap();
+
+        let standing = context(true, Vec2::ZERO, None);
+        let running = context(true, Vec2::new(-50., 0.), None);
+        let jumping = context(false, Vec2::new(50., 100.), None);
+        let sliding = context(false, Vec2::new(0., -60.), Some(WallMove::Sliding));
+
+        assert_eq!(next(&state_machine, None, false, &standing), Some("idle"));
+        assert_eq!(
+            next(&state_machine, Some("idle"), false, &running),
+            Some("run")
+        );
+        assert_eq!(
+            next(&state_machine, Some("run"), false, &jumping),
+            Some("jump")
+        );
+        assert_eq!(
+            next(&state_machine, Some("jump"), false, &sliding),
+            Some("slide")
+        );
+    }
+
+    #[test]
+    fn states_are_only_entered_from_the_listed_states() {
+        let state_machine: StateMachine = serde_yaml::from_str(STATES).unwrap();
+        let standing = context(true, Vec2::ZERO, None);
+
+        assert_eq!(next(&state_machine, None, false, &standing), Some("idle"));
+        assert_eq!(
+            next(&state_machine, Some("idle"), false, &standing),
+            Some("idle")
+        );
+        assert_eq!(
+            next(&state_machine, Some("jump"), false, &standing),
+            Some("land")
+        );
+    }
+
+    #[test]
+    fn states_until_finished_hold_until_their_clip_has_finished() {
+        let state_machine: StateMachine = serde_yaml::from_str(
+            "
+- name: slide
+  when:
+    wall: sliding
+- name: land
+  until_finished: true
+  when:
+    from: [jump]
+- name: run
+  when:
+    min_speed: 10
+- name: idle
+",
+        )
+        .unwrap();
+        let running = context(true, Vec2::new(50., 0.), None);
+        let sliding = context(false, Vec2::ZERO, Some(WallMove::Sliding));
+
+        assert_eq!(
+            next(&state_machine, Some("jump"), false, &running),
+            Some("land")
+        );
+        // Conditions of the locked state no longer need to hold, only earlier states can interrupt
+        assert_eq!(
+            next(&state_machine, Some("land"), false, &running),
+            Some("land")
+        );
+        assert_eq!(
+            next(&state_machine, Some("land"), false, &sliding),
+            Some("slide")
+        );
+        assert_eq!(
+            next(&state_machine, Some("land"), true, &running),
+            Some("run")
+        );
+    }
+
+    #[test]
+    fn unknown_wall_moves_are_rejected() {
+        let result = serde_yaml::from_str::<StateMachine>(
+            "
+- name: slide
+  when:
+    wall: slidding
+",
+        );
+
+        assert!(result.is_err());
+    }
+}
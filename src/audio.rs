@@ -0,0 +1,137 @@
+use bevy::audio::{SpatialScale, Volume};
+use bevy::prelude::*;
+
+use crate::loading::AudioAssets;
+use crate::GameState;
+
+pub struct InternalAudioPlugin;
+
+/// Distance in pixels between the ears of the camera, so sounds at the edges of the view
+/// play on one side only
+pub const LISTENER_EAR_GAP: f32 = 640.;
+
+/// Sounds within this many pixels of the camera play at full volume, and fade with distance beyond
+const FULL_VOLUME_DISTANCE: f32 = 320.;
+
+/// This plugin plays the music and the sound effects requested through [PlaySound] events.
+/// Sound effects are panned and faded by where they happen relative to the camera.
+impl Plugin for InternalAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioSettings>()
+            .insert_resource(SpatialScale::new_2d(1. / FULL_VOLUME_DISTANCE))
+            .add_event::<PlaySound>()
+            .add_systems(OnExit(GameState::Loading), start_music)
+            .add_systems(
+                Update,
+                (play_sounds, update_music_volume).run_if(not(in_state(GameState::Loading))),
+            );
+    }
+}
+
+/// Volume per category of sound, from 0 to 1
+#[derive(Resource, Clone, Debug)]
+pub struct AudioSettings {
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            music_volume: 0.5,
+            sfx_volume: 0.8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sound {
+    Shoot,
+    Jump,
+    Land,
+    Ricochet,
+    Hit,
+    Dash,
+}
+
+/// Send this event to play a sound effect where something happened
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlaySound {
+    pub sound: Sound,
+    pub position: Vec2,
+}
+
+impl PlaySound {
+    pub fn new(sound: Sound, position: Vec2) -> Self {
+        Self { sound, position }
+    }
+}
+
+#[derive(Component)]
+struct Music;
+
+impl AudioAssets {
+    fn sound(&self, sound: Sound) -> Option<&Handle<AudioSource>> {
+        match sound {
+            Sound::Shoot => self.shoot.as_ref(),
+            Sound::Jump => Some(&self.jump),
+            Sound::Land => self.land.as_ref(),
+            Sound::Ricochet => self.ricochet.as_ref(),
+            Sound::Hit => self.hit.as_ref(),
+            Sound::Dash => self.dash.as_ref(),
+        }
+    }
+}
+
+fn start_music(
+    mut commands: Commands,
+    audio_assets: Res<AudioAssets>,
+    settings: Res<AudioSettings>,
+) {
+    let Some(music) = &audio_assets.music else {
+        return;
+    };
+
+    commands
+        .spawn(AudioBundle {
+            source: music.clone(),
+            settings: PlaybackSettings::LOOP
+                .with_volume(Volume::new_relative(settings.music_volume)),
+        })
+        .insert(Music);
+}
+
+fn update_music_volume(settings: Res<AudioSettings>, query: Query<&AudioSink, With<Music>>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for sink in &query {
+        sink.set_volume(settings.music_volume);
+    }
+}
+
+fn play_sounds(
+    mut commands: Commands,
+    mut sound_events: EventReader<PlaySound>,
+    audio_assets: Res<AudioAssets>,
+    settings: Res<AudioSettings>,
+) {
+    for sound_event in sound_events.read() {
+        // Sounds without a file in `assets/audio` stay silent
+        let Some(source) = audio_assets.sound(sound_event.sound) else {
+            continue;
+        };
+
+        commands
+            .spawn(AudioBundle {
+                source: source.clone(),
+                settings: PlaybackSettings::DESPAWN
+                    .with_spatial(true)
+                    .with_volume(Volume::new_relative(settings.sfx_volume)),
+            })
+            .insert(TransformBundle::from_transform(
+                Transform::from_translation(sound_event.position.extend(0.)),
+            ));
+    }
+}
@@ -1,72 +1,341 @@
-use std::f32::consts::PI;
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::prelude::*;
+use rand::seq::SliceRandom;
 
-use bevy::prelude::*;
-use bevy_rapier2d::{prelude::*, rapier::dynamics::RigidBodyMassProps};
-use rand::{random, Rng};
-
-use crate::player::{Bullet, Lifetime, Player};
+use crate::damage::{Damage, Dead, Killed};
+use crate::decals::LeavesDecals;
+use crate::loading::EffectAssets;
+use crate::particles::{CollisionResponse, ParticleEffect, ParticleEmitter};
 
 pub struct BloodPlugin;
 
+/// Blood splatters on hits, and the blood that reaches a wall stays there as decals.
+/// How much blood flies where is tuned in a `.gore.yml` descriptor.
 impl Plugin for BloodPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, spawn_blood_on_hit);
+        app.init_asset::<Gore>()
+            .init_asset_loader::<GoreLoader>()
+            .add_systems(
+                Update,
+                (spawn_blood_on_hit, burst_on_kill).run_if(resource_exists::<EffectAssets>()),
+            );
     }
 }
 
-fn spawn_blood_on_hit(
-    mut commands: Commands,
-    mut events: EventReader<CollisionEvent>,
-    bullet_query: Query<(Entity, &Transform, &Velocity), With<Bullet>>,
-    player_query: Query<&Player>,
-) {
-    for collision_event in events.read() {
-        match collision_event {
-            CollisionEvent::Started(first, second, _) => {
-                let bullet = bullet_query.get(*first).or(bullet_query.get(*second));
-                let player = player_query.get(*first).or(player_query.get(*second));
-                if let (Ok((entity, transform, velocity)), Ok(_)) = (bullet, player) {
-                    let mut rng = rand::thread_rng();
-                    for _ in 0..100 {
-                        // Generate a random angle within 45 degrees in radians
-                        let angle = rng.gen_range(-PI / 8.0..=PI / 8.0);
-
-                        // Extract the x and y components of the velocity
-                        let x = velocity.linvel.x;
-                        let y = velocity.linvel.y;
-
-                        // Calculate the new x and y components using the rotation matrix formula
-                        let new_x = x * angle.cos() - y * angle.sin();
-                        let new_y = x * angle.sin() + y * angle.cos();
-
-                        // Create a new Velocity object with the new x and y components
-                        let new_velocity = Velocity::linear(
-                            Vec2::new(new_x, new_y) * (random::<f32>() * 0.5 + 0.5),
-                        );
-                        commands.spawn((
-                            SpriteBundle {
-                                transform: transform.clone(),
-                                sprite: Sprite {
-                                    color: Color::RED,
-                                    custom_size: Some(Vec2::splat(1.0)),
-                                    ..default()
-                                },
-                                ..default()
-                            },
-                            Blood,
-                            Lifetime::from_seconds(3.),
-                            RigidBody::Dynamic,
-                            AdditionalMassProperties::Mass(0.1),
-                            new_velocity,
-                        ));
-                    }
-                    commands.entity(entity).despawn();
+/// How players bleed, loaded from a `.gore.yml` descriptor such as `effects/blood.gore.yml`
+#[derive(Asset, TypePath, serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Gore {
+    /// Droplets per point of damage
+    pub droplets_per_damage: f32,
+    /// The most droplets a single hit can spill
+    pub max_droplets: u32,
+    /// Angle in degrees of the spray of a hit dealing no damage
+    pub spread: f32,
+    /// Degrees the spray widens by per point of damage
+    pub spread_per_damage: f32,
+    /// The most degrees the spray can widen by
+    pub max_spread: f32,
+    /// Speed of the droplets as a fraction of the bullet's speed, picked at random within the range
+    pub speed: [f32; 2],
+    /// Droplet sizes in pixels, picked at random for each color
+    pub sizes: Vec<f32>,
+    /// Shades of blood as `[red, green, blue]`, each getting an equal share of the droplets
+    pub colors: Vec<[f32; 3]>,
+    pub exit_wound: ExitWound,
+    pub headshot: Headshot,
+    pub kill: KillBurst,
+}
+
+/// Blood sprayed forward out of the far side of the body
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ExitWound {
+    /// Share of a hit's droplets that come out of the exit wound,
+    /// the rest splash back out of the entry wound
+    pub share: f32,
+    /// Distance in pixels from the entry to the exit wound
+    pub depth: f32,
+    /// Angle in degrees of the spray, widened by damage like the entry spray
+    pub spread: f32,
+}
+
+/// Hits to the head bleed more
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Headshot {
+    /// Height in pixels above the center of a player where the head starts
+    pub height: f32,
+    /// How many times more droplets a hit to the head spills
+    pub multiplier: f32,
+}
+
+/// What flies when a player is killed
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct KillBurst {
+    pub droplets: u32,
+    /// Speed of the droplets in pixels per second, picked at random within the range
+    pub speed: [f32; 2],
+    /// Chunks thrown out of the body
+    pub gibs: u32,
+    pub gib_size: f32,
+    pub gib_color: [f32; 3],
+    /// Speed of the gibs in pixels per second, picked at random within the range
+    pub gib_speed: [f32; 2],
+    /// Seconds before the gibs fade away
+    pub gib_lifetime: f32,
+}
+
+#[derive(Default)]
+pub struct GoreLoader;
+
+impl AssetLoader for GoreLoader {
+    type Asset = Gore;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let gore: Gore = serde_yaml::from_slice(&bytes)?;
+
+            if gore.colors.is_empty() || gore.sizes.is_empty() {
+                anyhow::bail!("Gore needs at least one color and one size");
+            }
+            if !(0. ..=1.).contains(&gore.exit_wound.share) {
+                anyhow::bail!("The exit wound share must be between 0 and 1");
+            }
+            for (name, [min, max]) in [
+                ("speed", gore.speed),
+                ("kill speed", gore.kill.speed),
+                ("gib speed", gore.kill.gib_speed),
+            ] {
+                if !min.is_finite() || !max.is_finite() || min > max {
+                    anyhow::bail!("The {name} range must go from low to high, got [{min}, {max}]");
                 }
             }
-            _ => {}
-        }
+            for (name, value) in [
+                ("spread", gore.spread),
+                ("spread per damage", gore.spread_per_damage),
+                ("max spread", gore.max_spread),
+                ("exit wound spread", gore.exit_wound.spread),
+                ("gib lifetime", gore.kill.gib_lifetime),
+            ] {
+                if !value.is_finite() || value < 0. {
+                    anyhow::bail!("The {name} must be zero or more, got {value}");
+                }
+            }
+
+            Ok(gore)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["gore.yml"]
     }
 }
 
 #[derive(Component)]
 pub struct Blood;
+
+/// A spray of droplets out of a wound
+struct Spray {
+    position: Vec2,
+    /// Middle of the cone the droplets fly out in
+    direction: Vec2,
+    count: u32,
+    /// Angle in degrees of the cone
+    spread: f32,
+    /// Speed of the droplets in pixels per second, picked at random within the range
+    speed: [f32; 2],
+    gravity: Vec2,
+}
+
+/// Seconds before droplets that never reach a wall, e.g. by flying out of the level, disappear
+const DROPLET_LIFETIME: f32 = 3.;
+
+impl Gore {
+    /// Spawns the droplets of a spray, shared between the colors
+    fn spray(&self, commands: &mut Commands, spray: Spray) {
+        let Spray {
+            position,
+            direction,
+            count,
+            spread,
+            speed,
+            gravity,
+        } = spray;
+        let mut rng = rand::thread_rng();
+        let shades = self.colors.len() as u32;
+
+        for (index, &[red, green, blue]) in self.colors.iter().enumerate() {
+            // The first colors take whatever doesn't divide evenly
+            let droplets = count / shades + u32::from((index as u32) < count % shades);
+            if droplets == 0 {
+                continue;
+            }
+
+            let effect = ParticleEffect {
+                burst: droplets,
+                lifetime: (DROPLET_LIFETIME, DROPLET_LIFETIME),
+                speed: (speed[0], speed[1]),
+                spread,
+                gravity,
+                colors: vec![Color::rgb(red, green, blue)],
+                sizes: vec![*self.sizes.choose(&mut rng).unwrap_or(&1.)],
+                collision: Some(CollisionResponse::Remove),
+                ..default()
+            };
+
+            commands.spawn((
+                ParticleEmitter::one_shot(effect),
+                SpatialBundle::from_transform(
+                    // In front of the players, like the bullets
+                    Transform::from_translation(position.extend(3.1))
+                        .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x))),
+                ),
+                Blood,
+                LeavesDecals,
+            ));
+        }
+    }
+}
+
+fn spawn_blood_on_hit(
+    mut commands: Commands,
+    mut damage_events: EventReader<Damage>,
+    target_query: Query<&Transform, Without<Dead>>,
+    effect_assets: Res<EffectAssets>,
+    gores: Res<Assets<Gore>>,
+    rapier_configuration: Res<RapierConfiguration>,
+) {
+    let Some(gore) = gores.get(&effect_assets.gore) else {
+        return;
+    };
+
+    for damage in damage_events.read() {
+        // Bodies that are already down don't bleed any more
+        let Ok(transform) = target_query.get(damage.target) else {
+            continue;
+        };
+        let headshot = damage.position.y - transform.translation.y >= gore.headshot.height;
+        let multiplier = if headshot {
+            gore.headshot.multiplier
+        } else {
+            1.
+        };
+
+        let count =
+            ((damage.amount * gore.droplets_per_damage * multiplier) as u32).min(gore.max_droplets);
+        let exit_count = (count as f32 * gore.exit_wound.share).round() as u32;
+        let widening = (damage.amount * gore.spread_per_damage).min(gore.max_spread);
+
+        let direction = damage.velocity.normalize_or_zero();
+        let bullet_speed = damage.velocity.length();
+        let speed = [gore.speed[0] * bullet_speed, gore.speed[1] * bullet_speed];
+
+        // Splashing back towards the shooter
+        gore.spray(
+            &mut commands,
+            Spray {
+                position: damage.position,
+                direction: -direction,
+                count: count - exit_count,
+                spread: gore.spread + widening,
+                speed,
+                gravity: rapier_configuration.gravity,
+            },
+        );
+        gore.spray(
+            &mut commands,
+            Spray {
+                position: damage.position + direction * gore.exit_wound.depth,
+                direction,
+                count: exit_count,
+                spread: gore.exit_wound.spread + widening,
+                speed,
+                gravity: rapier_configuration.gravity,
+            },
+        );
+    }
+}
+
+fn burst_on_kill(
+    mut commands: Commands,
+    mut killed_events: EventReader<Killed>,
+    target_query: Query<&Transform>,
+    effect_assets: Res<EffectAssets>,
+    gores: Res<Assets<Gore>>,
+    rapier_configuration: Res<RapierConfiguration>,
+) {
+    let Some(gore) = gores.get(&effect_assets.gore) else {
+        return;
+    };
+    let kill = &gore.kill;
+
+    for killed in killed_events.read() {
+        let direction = killed.velocity.normalize_or_zero();
+
+        gore.spray(
+            &mut commands,
+            Spray {
+                position: killed.position,
+                direction,
+                count: kill.droplets,
+                spread: 360.,
+                speed: kill.speed,
+                gravity: rapier_configuration.gravity,
+            },
+        );
+
+        if kill.gibs == 0 {
+            continue;
+        }
+
+        let [red, green, blue] = kill.gib_color;
+        let effect = ParticleEffect {
+            burst: kill.gibs,
+            lifetime: (kill.gib_lifetime * 0.8, kill.gib_lifetime),
+            speed: (kill.gib_speed[0], kill.gib_speed[1]),
+            spread: 120.,
+            gravity: rapier_configuration.gravity,
+            drag: 0.5,
+            colors: vec![
+                Color::rgb(red, green, blue),
+                Color::rgb(red, green, blue),
+                Color::rgba(red, green, blue, 0.),
+            ],
+            sizes: vec![kill.gib_size],
+            collision: Some(CollisionResponse::Bounce(0.3)),
+            ..default()
+        };
+        // Mostly upwards, leaning the way the killing bullet was going
+        let aim = (Vec2::Y + direction * 0.5).normalize();
+        // Out of the middle of the body
+        let origin = target_query
+            .get(killed.target)
+            .map_or(killed.position, |transform| {
+                transform.translation.truncate()
+            });
+
+        commands.spawn((
+            ParticleEmitter::one_shot(effect),
+            SpatialBundle::from_transform(
+                Transform::from_translation(origin.extend(3.1))
+                    .with_rotation(Quat::from_rotation_z(aim.y.atan2(aim.x))),
+            ),
+            Blood,
+        ));
+    }
+}
@@ -1,7 +1,8 @@
 use bevy::prelude::*;
 use bevy_pixel_camera::{PixelCameraPlugin, PixelViewport, PixelZoom};
 
-use crate::world::GameWorld;
+use crate::audio::LISTENER_EAR_GAP;
+use crate::world::{world_is_sized, GameWorld};
 
 pub struct CameraPlugin;
 
@@ -20,14 +21,19 @@ impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(PixelCameraPlugin)
             .add_systems(Startup, setup_camera)
-            .add_systems(Update, follow_targets);
+            .add_systems(Update, follow_targets.run_if(world_is_sized));
     }
 }
 fn setup_camera(mut commands: Commands, game_world: Res<GameWorld>) {
     let mut camera2d_bundle = Camera2dBundle::default();
     camera2d_bundle.transform.translation.x = game_world.width;
     camera2d_bundle.transform.translation.y = game_world.height;
-    commands.spawn((camera2d_bundle, PixelZoom::Fixed(2), PixelViewport));
+    commands.spawn((
+        camera2d_bundle,
+        PixelZoom::Fixed(2),
+        PixelViewport,
+        SpatialListener::new(LISTENER_EAR_GAP),
+    ));
 }
 
 fn follow_targets(
@@ -82,7 +88,7 @@ fn follow_targets(
         let delta =
             (center_x - camera_transform.translation.x) * time.delta_seconds() * follow_speed;
         let new_x = camera_transform.translation.x + delta;
-        let new_x = new_x.clamp(pixel_width / 2., game_world.width - pixel_width / 2.);
+        let new_x = clamp_to_world(new_x, pixel_width, game_world.width);
         camera_transform.translation.x = new_x;
     }
 
@@ -90,7 +96,19 @@ fn follow_targets(
         let delta =
             (center_y - camera_transform.translation.y) * time.delta_seconds() * follow_speed;
         let new_y = camera_transform.translation.y + delta;
-        let new_y = new_y.clamp(pixel_height / 2., game_world.height - pixel_height / 2.);
+        let new_y = clamp_to_world(new_y, pixel_height, game_world.height);
         camera_transform.translation.y = new_y;
     }
 }
+
+/// Keep the view inside the world, or centered on it if the world is smaller than the view
+fn clamp_to_world(position: f32, view_size: f32, world_size: f32) -> f32 {
+    let min = view_size / 2.;
+    let max = world_size - view_size / 2.;
+
+    if min > max {
+        world_size / 2.
+    } else {
+        position.clamp(min, max)
+    }
+}
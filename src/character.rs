@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::platforms::{Carried, DroppingThrough, OneWayPlatform, ONE_WAY_GROUP};
+use crate::{GameState, PHYSICS_STEP};
+
+pub struct CharacterPlugin;
+
+/// This plugin moves characters in `ControllerMode::Kinematic` with Rapier's character controller,
+/// which snaps them to the ground, keeps them off too steep slopes and lets them step up small ledges.
+/// Gameplay code keeps steering all characters through their `Velocity`, whatever the mode.
+impl Plugin for CharacterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, apply_controller_mode).add_systems(
+            PostUpdate,
+            (
+                drive_kinematic_characters.before(PhysicsSet::SyncBackend),
+                follow_kinematic_output.after(PhysicsSet::Writeback),
+            )
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// How a character is moved through the world
+#[derive(Component, Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum ControllerMode {
+    /// Simulated as a dynamic rigid body, pushed around by the physics engine
+    #[default]
+    Dynamic,
+    /// Moved by the character controller, only going where the world lets it
+    Kinematic,
+}
+
+impl ControllerMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            ControllerMode::Dynamic => ControllerMode::Kinematic,
+            ControllerMode::Kinematic => ControllerMode::Dynamic,
+        }
+    }
+}
+
+/// Steepest slope in degrees a character can walk up
+const MAX_SLOPE_CLIMB_ANGLE: f32 = 45.;
+
+/// Gentlest slope in degrees a character slides down when standing still
+const MIN_SLOPE_SLIDE_ANGLE: f32 = 30.;
+
+/// Highest ledge in pixels a character walks up without jumping
+const MAX_STEP_HEIGHT: f32 = 6.;
+
+/// Furthest in pixels a character is pulled down to stay on the ground, e.g. when walking down slopes
+const SNAP_TO_GROUND: f32 = 4.;
+
+fn character_controller() -> KinematicCharacterController {
+    KinematicCharacterController {
+        offset: CharacterLength::Absolute(0.5),
+        max_slope_climb_angle: MAX_SLOPE_CLIMB_ANGLE.to_radians(),
+        min_slope_slide_angle: MIN_SLOPE_SLIDE_ANGLE.to_radians(),
+        autostep: Some(CharacterAutostep {
+            max_height: CharacterLength::Absolute(MAX_STEP_HEIGHT),
+            min_width: CharacterLength::Absolute(2.),
+            include_dynamic_bodies: false,
+        }),
+        snap_to_ground: Some(CharacterLength::Absolute(SNAP_TO_GROUND)),
+        // Bullets and loose objects don't get in the way
+        filter_flags: QueryFilterFlags::EXCLUDE_SENSORS | QueryFilterFlags::EXCLUDE_DYNAMIC,
+        ..default()
+    }
+}
+
+fn apply_controller_mode(
+    mut commands: Commands,
+    query: Query<(Entity, &ControllerMode), Changed<ControllerMode>>,
+) {
+    for (entity, mode) in &query {
+        match mode {
+            ControllerMode::Dynamic => {
+                commands
+                    .entity(entity)
+                    .insert((RigidBody::Dynamic, ActiveCollisionTypes::default()))
+                    .remove::<(
+                        KinematicCharacterController,
+                        KinematicCharacterControllerOutput,
+                    )>();
+            }
+            ControllerMode::Kinematic => {
+                commands.entity(entity).insert((
+                    RigidBody::KinematicPositionBased,
+                    character_controller(),
+                    // Kinematic bodies otherwise don't touch level sensors, like the exit
+                    ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_FIXED,
+                ));
+            }
+        }
+    }
+}
+
+/// Turns the velocity of kinematic characters into movement for the character controller
+#[allow(clippy::type_complexity)]
+fn drive_kinematic_characters(
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        &mut Velocity,
+        &mut KinematicCharacterController,
+        Option<&Carried>,
+        Has<DroppingThrough>,
+    )>,
+    one_way_query: Query<(), With<OneWayPlatform>>,
+    rapier_context: Res<RapierContext>,
+    rapier_configuration: Res<RapierConfiguration>,
+) {
+    for (entity, transform, collider, mut velocity, mut controller, carried, dropping_through) in
+        &mut query
+    {
+        // Kinematic bodies don't fall by themselves
+        velocity.linvel += rapier_configuration.gravity * PHYSICS_STEP;
+        let carried = carried.map_or(Vec2::ZERO, |carried| carried.velocity);
+        controller.translation = Some((velocity.linvel + carried) * PHYSICS_STEP);
+
+        // One-way platforms only hold up characters that land on them from above
+        let mut inside_one_way = false;
+        rapier_context.intersections_with_shape(
+            transform.translation.truncate(),
+            0.,
+            collider,
+            QueryFilter::only_fixed().exclude_collider(entity),
+            |other| {
+                inside_one_way = one_way_query.contains(other);
+                !inside_one_way
+            },
+        );
+        let pass_one_way = dropping_through || inside_one_way || velocity.linvel.y > 0.;
+        controller.filter_groups =
+            pass_one_way.then(|| CollisionGroups::new(Group::ALL, !ONE_WAY_GROUP));
+    }
+}
+
+/// Keeps the velocity of kinematic characters in line with how far they actually got,
+/// so they stop when running into walls, ceilings and the ground.
+/// Being carried along by a platform doesn't count towards their own velocity.
+fn follow_kinematic_output(
+    mut query: Query<(
+        &mut Velocity,
+        &KinematicCharacterControllerOutput,
+        Option<&Carried>,
+    )>,
+) {
+    for (mut velocity, output, carried) in &mut query {
+        let carried = carried.map_or(Vec2::ZERO, |carried| carried.velocity);
+        let desired = output.desired_translation / PHYSICS_STEP - carried;
+        let moved = output.effective_translation / PHYSICS_STEP - carried;
+
+        velocity.linvel.x = limit_to(moved.x, desired.x);
+        velocity.linvel.y = if output.grounded && desired.y <= 0. {
+            0.
+        } else {
+            limit_to(moved.y, desired.y)
+        };
+    }
+}
+
+/// Clamps `moved` between zero and `desired`, so stepping up and snapping to the ground
+/// don't turn into speed
+fn limit_to(moved: f32, desired: f32) -> f32 {
+    if desired >= 0. {
+        moved.clamp(0., desired)
+    } else {
+        moved.clamp(desired, 0.)
+    }
+}
@@ -20,4 +20,13 @@ impl Facing {
     pub fn set(&mut self, facing: Facing) {
         *self = facing;
     }
+
+    /// 1 when facing right, -1 when facing left
+    pub fn direction(&self) -> f32 {
+        if self.is_left() {
+            -1.
+        } else {
+            1.
+        }
+    }
 }
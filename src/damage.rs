@@ -0,0 +1,342 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::animation::{Animation, AnimationFinished};
+use crate::audio::{PlaySound, Sound};
+use crate::player::{Bullet, Health, Lifetime, Player, Standing, Team, SPAWN_HEIGHT_OFFSET};
+use crate::weapon::Ammo;
+use crate::world::SpawnPoints;
+use crate::{GameState, PHYSICS_STEP};
+
+pub struct DamagePlugin;
+
+/// How long a dead player stays down after the death animation before respawning
+const RESPAWN_DELAY: f32 = 3.;
+
+/// Longest the respawn countdown waits for the death animation, in case it never finishes
+const MAX_DYING_TIME: f32 = 2.;
+
+/// This plugin turns bullet hits into damage, and handles death and respawning
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Damage>()
+            .add_event::<Killed>()
+            .init_resource::<DamageRules>()
+            .add_systems(
+                Update,
+                (
+                    wear_off_invulnerability,
+                    bullet_hits,
+                    apply_damage,
+                    start_respawn_countdown,
+                    respawn_dead,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Sent whenever something is hit by something that hurts
+#[derive(Event, Clone, Debug)]
+pub struct Damage {
+    pub target: Entity,
+    pub amount: f32,
+    /// Where the hit landed
+    pub position: Vec2,
+    /// The velocity of whatever caused the hit
+    pub velocity: Vec2,
+}
+
+/// Sent when a hit takes the last of a player's health
+#[derive(Event, Clone, Debug)]
+pub struct Killed {
+    pub target: Entity,
+    /// Where the killing hit landed
+    pub position: Vec2,
+    /// The velocity of whatever caused the killing hit
+    pub velocity: Vec2,
+}
+
+/// Decides which bullets can hurt which players.
+/// Bullets that can't hurt a player pass straight through them.
+#[derive(Resource, Clone, Debug)]
+pub struct DamageRules {
+    /// Whether players can be hurt by their own bullets, e.g. after a ricochet
+    pub self_damage: bool,
+    /// Whether players can be hurt by bullets fired by their teammates
+    pub friendly_fire: bool,
+    /// How long, in seconds, a bullet passes through the player that fired it,
+    /// so a shooter running forward doesn't catch their own bullet
+    pub owner_grace_period: f32,
+}
+
+impl Default for DamageRules {
+    fn default() -> Self {
+        Self {
+            self_damage: true,
+            friendly_fire: false,
+            owner_grace_period: 0.25,
+        }
+    }
+}
+
+impl DamageRules {
+    /// Whether `bullet`, fired `age` seconds ago, can hit the player `target` on `team`
+    pub fn can_hit(&self, bullet: &Bullet, age: f32, target: Entity, team: &Team) -> bool {
+        if target == bullet.owner {
+            self.self_damage && age >= self.owner_grace_period
+        } else if *team == bullet.team {
+            self.friendly_fire
+        } else {
+            true
+        }
+    }
+}
+
+/// Present on players whose health has run out, until they respawn
+#[derive(Component, Reflect, Clone, Debug)]
+pub struct Dead {
+    pub respawn_timer: Timer,
+    /// Starts the respawn countdown if the death animation takes too long,
+    /// e.g. because it loops or gets interrupted
+    pub dying_timer: Timer,
+}
+
+impl Default for Dead {
+    fn default() -> Self {
+        Self {
+            respawn_timer: Timer::from_seconds(RESPAWN_DELAY, TimerMode::Once),
+            dying_timer: Timer::from_seconds(MAX_DYING_TIME, TimerMode::Once),
+        }
+    }
+}
+
+/// Present on players that bullets pass straight through, e.g. while dashing
+#[derive(Component, Clone, Debug)]
+pub struct Invulnerable {
+    pub timer: Timer,
+}
+
+impl Invulnerable {
+    pub fn from_seconds(seconds: f32) -> Self {
+        Self {
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+        }
+    }
+}
+
+fn wear_off_invulnerability(mut commands: Commands, mut query: Query<(Entity, &mut Invulnerable)>) {
+    let delta = Duration::from_secs_f32(PHYSICS_STEP);
+
+    for (entity, mut invulnerable) in &mut query {
+        if invulnerable.timer.tick(delta).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn bullet_hits(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<Damage>,
+    mut sound_events: EventWriter<PlaySound>,
+    bullet_query: Query<(Entity, &Bullet, &Lifetime, &Transform, &Velocity)>,
+    player_query: Query<(Entity, &Team), (With<Player>, Without<Invulnerable>)>,
+    damage_rules: Res<DamageRules>,
+) {
+    for collision_event in collision_events.read() {
+        if let CollisionEvent::Started(first, second, _) = collision_event {
+            let bullet = bullet_query.get(*first).or(bullet_query.get(*second));
+            let player = player_query.get(*first).or(player_query.get(*second));
+            if let (Ok((entity, bullet, lifetime, transform, velocity)), Ok((target, team))) =
+                (bullet, player)
+            {
+                if !damage_rules.can_hit(bullet, lifetime.timer.elapsed_secs(), target, team) {
+                    continue;
+                }
+
+                damage_events.send(Damage {
+                    target,
+                    amount: bullet.damage,
+                    position: transform.translation.truncate(),
+                    velocity: velocity.linvel,
+                });
+                sound_events.send(PlaySound::new(Sound::Hit, transform.translation.truncate()));
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<Damage>,
+    mut killed_events: EventWriter<Killed>,
+    mut query: Query<(&mut Health, &mut Velocity, &mut Animation), Without<Dead>>,
+) {
+    for damage in damage_events.read() {
+        let Ok((mut health, mut velocity, mut animation)) = query.get_mut(damage.target) else {
+            continue;
+        };
+
+        // Several hits can land in the same frame, so only the first one that kills counts
+        if health.health <= 0. {
+            continue;
+        }
+
+        health.health -= damage.amount;
+
+        if health.health <= 0. {
+            health.health = 0.;
+            velocity.linvel.x = 0.;
+
+            let mut dead = Dead::default();
+            if animation.animations.contains_key("dying") {
+                animation.play("dying");
+                // The countdown starts once the body hits the floor
+                dead.respawn_timer.pause();
+            }
+            // Dying ends any dash, and its invulnerability with it
+            commands
+                .entity(damage.target)
+                .insert(dead)
+                .remove::<Invulnerable>();
+            killed_events.send(Killed {
+                target: damage.target,
+                position: damage.position,
+                velocity: damage.velocity,
+            });
+        }
+    }
+}
+
+fn start_respawn_countdown(
+    mut finished_events: EventReader<AnimationFinished>,
+    mut query: Query<&mut Dead>,
+) {
+    for finished in finished_events.read() {
+        if finished.animation != "dying" {
+            continue;
+        }
+        if let Ok(mut dead) = query.get_mut(finished.entity) {
+            dead.respawn_timer.unpause();
+        }
+    }
+
+    let delta = Duration::from_secs_f32(PHYSICS_STEP);
+    for mut dead in &mut query {
+        if dead.respawn_timer.paused() && dead.dying_timer.tick(delta).finished() {
+            dead.respawn_timer.unpause();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn respawn_dead(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &mut Dead,
+            &mut Health,
+            &mut Transform,
+            &mut Velocity,
+            &mut Animation,
+            &mut Ammo,
+        ),
+        With<Player>,
+    >,
+    spawn_points: Res<SpawnPoints>,
+) {
+    let delta = Duration::from_secs_f32(PHYSICS_STEP);
+
+    for (entity, mut dead, mut health, mut transform, mut velocity, mut animation, mut ammo) in
+        &mut query
+    {
+        if !dead.respawn_timer.tick(delta).finished() {
+            continue;
+        }
+
+        let Some(spawn_point) = spawn_points.random() else {
+            continue;
+        };
+
+        transform.translation.x = spawn_point.x;
+        transform.translation.y = spawn_point.y + SPAWN_HEIGHT_OFFSET;
+        *velocity = Velocity::zero();
+        health.health = health.max;
+        ammo.refill();
+        animation.play("idle");
+        commands
+            .entity(entity)
+            .insert(Standing::default())
+            .remove::<(Dead, Invulnerable)>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHOOTER: Entity = Entity::PLACEHOLDER;
+
+    fn bullet() -> Bullet {
+        Bullet {
+            damage: 10.,
+            owner: SHOOTER,
+            team: Team(0),
+            bounces_left: 0,
+        }
+    }
+
+    #[test]
+    fn bullets_hit_opponents() {
+        let rules = DamageRules::default();
+
+        assert!(rules.can_hit(&bullet(), 0., Entity::from_raw(1), &Team(1)));
+    }
+
+    #[test]
+    fn bullets_hit_teammates_only_with_friendly_fire() {
+        let teammate = Entity::from_raw(1);
+        let rules = DamageRules {
+            friendly_fire: false,
+            ..default()
+        };
+        assert!(!rules.can_hit(&bullet(), 1., teammate, &Team(0)));
+
+        let rules = DamageRules {
+            friendly_fire: true,
+            ..default()
+        };
+        assert!(rules.can_hit(&bullet(), 1., teammate, &Team(0)));
+    }
+
+    #[test]
+    fn bullets_hit_their_owner_after_the_grace_period() {
+        let rules = DamageRules {
+            self_damage: true,
+            owner_grace_period: 0.25,
+            ..default()
+        };
+
+        assert!(!rules.can_hit(&bullet(), 0., SHOOTER, &Team(0)));
+        assert!(!rules.can_hit(&bullet(), 0.2, SHOOTER, &Team(0)));
+        assert!(rules.can_hit(&bullet(), 0.25, SHOOTER, &Team(0)));
+    }
+
+    #[test]
+    fn bullets_never_hit_their_owner_without_self_damage() {
+        let rules = DamageRules {
+            self_damage: false,
+            friendly_fire: true,
+            ..default()
+        };
+
+        assert!(!rules.can_hit(&bullet(), 10., SHOOTER, &Team(0)));
+    }
+}
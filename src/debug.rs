@@ -1,14 +1,27 @@
 use bevy::prelude::*;
 use bevy_rapier2d::render::DebugRenderContext;
 
+use crate::character::ControllerMode;
+use crate::loading::WeaponAssets;
+use crate::player::{Gun, Player};
+use crate::progression::AdvanceLevel;
 use crate::screen_diags::ScreenDiagsState;
+use crate::weapon::{Arsenal, EquipWeapon};
 
 pub struct DebugPlugin;
 
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, set_defaults)
-            .add_systems(Update, (toggle_rapier_debug, toggle_fps));
+        app.add_systems(Startup, set_defaults).add_systems(
+            Update,
+            (
+                toggle_rapier_debug,
+                toggle_fps,
+                skip_level,
+                toggle_controller_mode,
+                cycle_weapons.run_if(resource_exists::<WeaponAssets>()),
+            ),
+        );
     }
 }
 
@@ -38,3 +51,52 @@ fn toggle_fps(mut screen_diags_state: ResMut<ScreenDiagsState>, input: Res<Input
         }
     }
 }
+
+fn skip_level(mut advance_events: EventWriter<AdvanceLevel>, input: Res<Input<KeyCode>>) {
+    if input.just_pressed(KeyCode::F2) {
+        advance_events.send_default();
+    }
+}
+
+/// Switches every player between the dynamic and kinematic character controllers
+fn toggle_controller_mode(mut query: Query<&mut ControllerMode>, input: Res<Input<KeyCode>>) {
+    if input.just_pressed(KeyCode::F4) {
+        for mut mode in &mut query {
+            *mode = mode.toggled();
+        }
+    }
+}
+
+/// Gives every player the next weapon in the arsenal
+fn cycle_weapons(
+    mut equip_events: EventWriter<EquipWeapon>,
+    input: Res<Input<KeyCode>>,
+    player_query: Query<Entity, With<Player>>,
+    gun_query: Query<&Gun>,
+    weapon_assets: Res<WeaponAssets>,
+    arsenals: Res<Assets<Arsenal>>,
+) {
+    if !input.just_pressed(KeyCode::F3) {
+        return;
+    }
+    let Some(arsenal) = arsenals.get(&weapon_assets.arsenal) else {
+        return;
+    };
+    let Some(current) = gun_query.iter().next() else {
+        return;
+    };
+
+    let mut names: Vec<&String> = arsenal.weapons.keys().collect();
+    names.sort();
+    let index = names
+        .iter()
+        .position(|&name| *name == current.weapon_name)
+        .map_or(0, |index| (index + 1) % names.len());
+
+    for player in &player_query {
+        equip_events.send(EquipWeapon {
+            player,
+            weapon: names[index].clone(),
+        });
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::sprite::Anchor;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::particles::{ParticleCollision, ParticleSystems};
+
+pub struct DecalsPlugin;
+
+/// This plugin keeps marks left on the level, like blood splatter and bullet holes,
+/// painted into a texture covering the level so they can build up without keeping entities around
+impl Plugin for DecalsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PaintDecal>()
+            .add_systems(Update, (spawn_decal_canvas, paint_decals).chain())
+            .add_systems(PostUpdate, paint_particle_collisions.after(ParticleSystems));
+    }
+}
+
+/// Send this event to leave a mark on the level
+#[derive(Event, Clone, Debug)]
+pub struct PaintDecal {
+    /// Center of the mark in the world
+    pub position: Vec2,
+    /// Width and height of the mark in pixels
+    pub size: i32,
+    pub color: Color,
+}
+
+/// Put on a [ParticleEmitter](crate::particles::ParticleEmitter) to paint its particles
+/// onto the walls they hit
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct LeavesDecals;
+
+/// Between the level tiles and the players, relative to the level
+const CANVAS_Z: f32 = 2.9;
+
+/// The texture covering a level that decals are painted into
+#[derive(Component)]
+pub struct DecalCanvas {
+    pub image: Handle<Image>,
+}
+
+impl DecalCanvas {
+    /// Paints a square of `size` pixels at `position`, relative to the bottom left corner of the canvas
+    fn paint(image: &mut Image, position: Vec2, size: i32, color: Color) {
+        let width = image.texture_descriptor.size.width as i32;
+        let height = image.texture_descriptor.size.height as i32;
+        let [red, green, blue, alpha] = color.as_rgba_u8();
+
+        let left = position.x.floor() as i32 - size / 2;
+        let top = height - 1 - position.y.floor() as i32 - size / 2;
+
+        for y in top..top + size {
+            for x in left..left + size {
+                if x < 0 || y < 0 || x >= width || y >= height {
+                    continue;
+                }
+                let offset = ((y * width + x) * 4) as usize;
+                image.data[offset..offset + 4].copy_from_slice(&[red, green, blue, alpha]);
+            }
+        }
+    }
+}
+
+fn spawn_decal_canvas(
+    mut commands: Commands,
+    mut level_events: EventReader<LevelEvent>,
+    mut images: ResMut<Assets<Image>>,
+    level_query: Query<(Entity, &LevelIid)>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    for level_event in level_events.read() {
+        let LevelEvent::Spawned(level_iid) = level_event else {
+            continue;
+        };
+        let Some((level_entity, _)) = level_query.iter().find(|(_, iid)| *iid == level_iid) else {
+            continue;
+        };
+        let ldtk_project = ldtk_project_assets
+            .get(ldtk_projects.single())
+            .expect("Project should be loaded if level has spawned");
+        let level = ldtk_project
+            .as_standalone()
+            .get_loaded_level_by_iid(&level_iid.to_string())
+            .expect("Spawned level should exist in LDtk project");
+
+        let image = images.add(Image::new_fill(
+            Extent3d {
+                width: *level.px_wid() as u32,
+                height: *level.px_hei() as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 0],
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+
+        // As a child of the level, the canvas is despawned together with it
+        commands.entity(level_entity).with_children(|level| {
+            level
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        anchor: Anchor::BottomLeft,
+                        ..default()
+                    },
+                    texture: image.clone(),
+                    transform: Transform::from_xyz(0., 0., CANVAS_Z),
+                    ..default()
+                })
+                .insert(DecalCanvas { image });
+        });
+    }
+}
+
+fn paint_decals(
+    mut paint_events: EventReader<PaintDecal>,
+    canvas_query: Query<(&DecalCanvas, &GlobalTransform)>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for paint in paint_events.read() {
+        for (canvas, canvas_transform) in &canvas_query {
+            if let Some(image) = images.get_mut(&canvas.image) {
+                DecalCanvas::paint(
+                    image,
+                    paint.position - canvas_transform.translation().truncate(),
+                    paint.size,
+                    paint.color,
+                );
+            }
+        }
+    }
+}
+
+fn paint_particle_collisions(
+    mut collision_events: EventReader<ParticleCollision>,
+    mut paint_events: EventWriter<PaintDecal>,
+    emitter_query: Query<(), With<LeavesDecals>>,
+) {
+    for collision in collision_events.read() {
+        if !emitter_query.contains(collision.emitter) {
+            continue;
+        }
+
+        let size = collision.size.round() as i32;
+        paint_events.send(PaintDecal {
+            // Just inside the wall, on the surface that was hit
+            position: collision.position - collision.normal * size as f32 / 2.,
+            size,
+            color: collision.color,
+        });
+    }
+}
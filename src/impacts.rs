@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use crate::decals::PaintDecal;
+use crate::particles::{CollisionResponse, ParticleEffect, ParticleEmitter};
+
+pub struct ImpactsPlugin;
+
+/// This plugin shows bullets hitting walls, with sparks and bullet holes
+impl Plugin for ImpactsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ImpactSettings>()
+            .add_event::<BulletImpact>()
+            .add_systems(Update, spawn_impact_effects);
+    }
+}
+
+/// Tunes the effects of bullets hitting walls
+#[derive(Resource, Clone, Debug)]
+pub struct ImpactSettings {
+    /// Sparks thrown off by each impact
+    pub sparks: u32,
+    /// Whether impacts leave bullet holes in the walls
+    pub bullet_holes: bool,
+}
+
+impl Default for ImpactSettings {
+    fn default() -> Self {
+        Self {
+            sparks: 8,
+            bullet_holes: true,
+        }
+    }
+}
+
+/// Sent when a bullet hits a wall
+#[derive(Event, Clone, Debug)]
+pub struct BulletImpact {
+    pub position: Vec2,
+    /// Points out of the wall that was hit
+    pub normal: Vec2,
+}
+
+const BULLET_HOLE_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+
+fn spawn_impact_effects(
+    mut commands: Commands,
+    mut impact_events: EventReader<BulletImpact>,
+    mut paint_events: EventWriter<PaintDecal>,
+    settings: Res<ImpactSettings>,
+) {
+    for impact in impact_events.read() {
+        let effect = ParticleEffect {
+            burst: settings.sparks,
+            lifetime: (0.1, 0.3),
+            speed: (60., 160.),
+            spread: 120.,
+            gravity: Vec2::new(0., -300.),
+            drag: 3.,
+            colors: vec![
+                Color::rgb(1., 1., 0.8),
+                Color::rgb(1., 0.8, 0.2),
+                Color::rgba(1., 0.4, 0., 0.),
+            ],
+            sizes: vec![1.],
+            collision: Some(CollisionResponse::Bounce(0.4)),
+            ..default()
+        };
+
+        commands.spawn((
+            ParticleEmitter::one_shot(effect),
+            SpatialBundle::from_transform(
+                // In front of the players, like the bullets
+                Transform::from_translation(impact.position.extend(3.1)).with_rotation(
+                    Quat::from_rotation_z(impact.normal.y.atan2(impact.normal.x)),
+                ),
+            ),
+        ));
+
+        if settings.bullet_holes {
+            paint_events.send(PaintDecal {
+                // Just inside the wall
+                position: impact.position - impact.normal,
+                size: 1,
+                color: BULLET_HOLE_COLOR,
+            });
+        }
+    }
+}
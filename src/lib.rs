@@ -7,44 +7,70 @@ use bevy_rapier2d::prelude::*;
 use bevy_rapier2d::render::RapierDebugRenderPlugin;
 use blood::BloodPlugin;
 use camera::CameraPlugin;
+use character::CharacterPlugin;
 use components::facing::Facing;
+use damage::DamagePlugin;
 use debug::DebugPlugin;
+use decals::DecalsPlugin;
 use fps::FpsPlugin;
+use impacts::ImpactsPlugin;
 use leafwing_input_manager::prelude::*;
 
 use animation::AnimationPlugin;
+use audio::InternalAudioPlugin;
 use loading::LoadingPlugin;
+use lobby::LobbyPlugin;
+use movement::MovementPlugin;
+use particles::ParticlesPlugin;
+use physics_hooks::GamePhysicsHooks;
 use platforms::PlatformsPlugin;
 use player::PlayerPlugin;
+use progression::ProgressionPlugin;
+use weapon::WeaponPlugin;
 use world::WorldPlugin;
 
 mod animation;
+mod audio;
 mod blood;
 mod camera;
+mod character;
 mod components;
+mod damage;
 mod debug;
+mod decals;
 mod fps;
+mod impacts;
 mod loading;
+mod lobby;
+mod movement;
+mod particles;
+mod physics_hooks;
 mod platforms;
 mod player;
+mod progression;
 mod screen_diags;
+mod weapon;
 mod world;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Copy, States, Default)]
 enum GameState {
     #[default]
     Loading,
+    Joining,
     Playing,
+    LevelTransition,
 }
 
 pub struct GamePlugin;
 
-#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
+#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect, serde::Deserialize)]
 enum PlayerAction {
     Move,
     Aim,
     Jump,
     Shoot,
+    Reload,
+    Dash,
 }
 
 const PIXELS_PER_METER: f32 = 64.;
@@ -61,19 +87,29 @@ impl Plugin for GamePlugin {
             .add_state::<GameState>()
             .add_plugins(LdtkPlugin)
             .add_plugins(LoadingPlugin)
+            .add_plugins(LobbyPlugin)
             .add_plugins(PlayerPlugin)
+            .add_plugins(CharacterPlugin)
+            .add_plugins(MovementPlugin)
+            .add_plugins(WeaponPlugin)
             .add_plugins(WorldPlugin)
+            .add_plugins(ProgressionPlugin)
             .add_plugins(AnimationPlugin)
             .add_plugins(PlatformsPlugin)
             .add_plugins(CameraPlugin)
             .add_plugins(FpsPlugin)
+            .add_plugins(DamagePlugin)
+            .add_plugins(InternalAudioPlugin)
+            .add_plugins(DecalsPlugin)
+            .add_plugins(ParticlesPlugin)
             .add_plugins(BloodPlugin)
+            .add_plugins(ImpactsPlugin)
             .add_plugins(leafwing_input_manager::prelude::InputManagerPlugin::<
                 PlayerAction,
             >::default())
             .add_plugins(RapierDebugRenderPlugin::default())
             .add_plugins(DebugPlugin)
-            .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(
+            .add_plugins(RapierPhysicsPlugin::<GamePhysicsHooks>::pixels_per_meter(
                 PIXELS_PER_METER,
             ))
             .insert_resource(RapierConfiguration {
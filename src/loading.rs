@@ -1,6 +1,9 @@
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 
+use crate::animation::AnimationSheet;
+use crate::blood::Gore;
+use crate::weapon::Arsenal;
 use crate::GameState;
 
 pub struct LoadingPlugin;
@@ -12,9 +15,12 @@ impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<TextureAtlasSprite>().add_loading_state(
             LoadingState::new(GameState::Loading)
-                .continue_to_state(GameState::Playing)
+                .continue_to_state(GameState::Joining)
                 .load_collection::<FontAssets>()
-                .load_collection::<TextureAssets>(),
+                .load_collection::<AudioAssets>()
+                .load_collection::<TextureAssets>()
+                .load_collection::<WeaponAssets>()
+                .load_collection::<EffectAssets>(),
         );
     }
 }
@@ -28,15 +34,45 @@ pub struct FontAssets {
     pub fira_sans: Handle<Font>,
 }
 
+/// Sounds are optional, so new ones can be dropped into `assets/audio` as they are made
+#[derive(AssetCollection, Resource)]
+pub struct AudioAssets {
+    #[asset(path = "audio/music.ogg", optional)]
+    pub music: Option<Handle<AudioSource>>,
+    #[asset(path = "audio/shoot.ogg", optional)]
+    pub shoot: Option<Handle<AudioSource>>,
+    #[asset(path = "audio/flying.ogg")]
+    pub jump: Handle<AudioSource>,
+    #[asset(path = "audio/land.ogg", optional)]
+    pub land: Option<Handle<AudioSource>>,
+    #[asset(path = "audio/ricochet.ogg", optional)]
+    pub ricochet: Option<Handle<AudioSource>>,
+    #[asset(path = "audio/hit.ogg", optional)]
+    pub hit: Option<Handle<AudioSource>>,
+    #[asset(path = "audio/dash.ogg", optional)]
+    pub dash: Option<Handle<AudioSource>>,
+}
+
 #[derive(AssetCollection, Resource)]
 pub struct TextureAssets {
-    #[asset(texture_atlas(tile_size_x = 32., tile_size_y = 48., columns = 6, rows = 5))]
-    #[asset(path = "textures/cyborg.png")]
-    pub cyborg: Handle<TextureAtlas>,
+    #[asset(path = "textures/cyborg.yml")]
+    pub cyborg: Handle<AnimationSheet>,
+    #[asset(path = "textures/shoot_effect.yml")]
+    pub shoot_effect: Handle<AnimationSheet>,
     #[asset(path = "textures/hand.png")]
     pub hand: Handle<Image>,
-    #[asset(path = "textures/gun.png")]
-    pub gun: Handle<Image>,
     #[asset(path = "textures/bullet.png")]
     pub bullet: Handle<Image>,
 }
+
+#[derive(AssetCollection, Resource)]
+pub struct WeaponAssets {
+    #[asset(path = "weapons/arsenal.weapons.yml")]
+    pub arsenal: Handle<Arsenal>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct EffectAssets {
+    #[asset(path = "effects/blood.gore.yml")]
+    pub gore: Handle<Gore>,
+}
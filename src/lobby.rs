@@ -0,0 +1,253 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::loading::FontAssets;
+use crate::player::Team;
+use crate::{GameState, PlayerAction};
+
+pub struct LobbyPlugin;
+
+pub const MAX_PLAYERS: usize = 4;
+
+/// Body tints telling the players apart, in join order
+const PLAYER_TINTS: [Color; MAX_PLAYERS] = [
+    Color::WHITE,
+    Color::rgb(1., 0.6, 0.6),
+    Color::rgb(0.6, 0.8, 1.),
+    Color::rgb(0.7, 1., 0.6),
+];
+
+/// This plugin shows the "press to join" screen during the State `GameState::Joining`,
+/// collecting one set of controls per local player
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Lobby>()
+            .add_systems(OnEnter(GameState::Joining), spawn_lobby_screen)
+            .add_systems(
+                Update,
+                (join_players, update_lobby_text, start_game)
+                    .chain()
+                    .run_if(in_state(GameState::Joining)),
+            )
+            .add_systems(OnExit(GameState::Joining), despawn_lobby_screen);
+    }
+}
+
+/// The input device a local player uses
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Controls {
+    /// WASD to move, Space to jump, J or the left mouse button to shoot, R to reload, K to dash
+    Wasd,
+    /// Arrow keys to move, Right Ctrl to jump, Right Shift to shoot, Right Alt to reload,
+    /// Slash to dash
+    ArrowKeys,
+    Gamepad(Gamepad),
+}
+
+impl Controls {
+    pub fn input_map(&self) -> InputMap<PlayerAction> {
+        let mut input_map = InputMap::default();
+
+        match self {
+            Controls::Wasd => {
+                input_map
+                    .insert(KeyCode::J, PlayerAction::Shoot)
+                    .insert(MouseButton::Left, PlayerAction::Shoot)
+                    .insert(VirtualDPad::wasd(), PlayerAction::Move)
+                    .insert(KeyCode::Space, PlayerAction::Jump)
+                    .insert(KeyCode::R, PlayerAction::Reload)
+                    .insert(KeyCode::K, PlayerAction::Dash);
+            }
+            Controls::ArrowKeys => {
+                input_map
+                    .insert(KeyCode::ShiftRight, PlayerAction::Shoot)
+                    .insert(VirtualDPad::arrow_keys(), PlayerAction::Move)
+                    .insert(KeyCode::ControlRight, PlayerAction::Jump)
+                    .insert(KeyCode::AltRight, PlayerAction::Reload)
+                    .insert(KeyCode::Slash, PlayerAction::Dash);
+            }
+            Controls::Gamepad(gamepad) => {
+                input_map
+                    .insert(GamepadButtonType::RightTrigger2, PlayerAction::Shoot)
+                    .insert(GamepadButtonType::West, PlayerAction::Shoot)
+                    .insert(DualAxis::left_stick(), PlayerAction::Move)
+                    .insert(VirtualDPad::dpad(), PlayerAction::Move)
+                    .insert(GamepadButtonType::South, PlayerAction::Jump)
+                    .insert(GamepadButtonType::North, PlayerAction::Reload)
+                    .insert(GamepadButtonType::East, PlayerAction::Dash)
+                    .set_gamepad(*gamepad);
+            }
+        }
+
+        input_map
+    }
+
+    fn description(&self) -> String {
+        match self {
+            Controls::Wasd => String::from("Keyboard (WASD)"),
+            Controls::ArrowKeys => String::from("Keyboard (arrow keys)"),
+            Controls::Gamepad(gamepad) => format!("Gamepad {}", gamepad.id + 1),
+        }
+    }
+}
+
+/// A local player that has joined the game
+#[derive(Clone, Debug)]
+pub struct LobbyPlayer {
+    pub controls: Controls,
+    pub tint: Color,
+}
+
+/// The local players, in join order
+#[derive(Resource, Default, Debug)]
+pub struct Lobby {
+    pub players: Vec<LobbyPlayer>,
+    /// Split the players into two teams instead of everyone for themselves
+    pub teams: bool,
+}
+
+impl Lobby {
+    /// The team of the player with the given id
+    pub fn team(&self, id: usize) -> Team {
+        if self.teams {
+            Team(id % 2)
+        } else {
+            Team(id)
+        }
+    }
+
+    fn join(&mut self, controls: Controls) {
+        if self.players.len() >= MAX_PLAYERS
+            || self
+                .players
+                .iter()
+                .any(|player| player.controls == controls)
+        {
+            return;
+        }
+
+        self.players.push(LobbyPlayer {
+            controls,
+            tint: PLAYER_TINTS[self.players.len()],
+        });
+    }
+}
+
+#[derive(Component)]
+struct LobbyScreen;
+
+#[derive(Component)]
+struct LobbyText;
+
+fn spawn_lobby_screen(mut commands: Commands, fonts: Res<FontAssets>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(LobbyScreen)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: fonts.fira_sans.clone(),
+                        font_size: 32.,
+                        color: Color::WHITE,
+                    },
+                ))
+                .insert(LobbyText);
+        });
+}
+
+fn despawn_lobby_screen(mut commands: Commands, query: Query<Entity, With<LobbyScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn join_players(
+    mut lobby: ResMut<Lobby>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+) {
+    if keyboard.just_pressed(KeyCode::Space) {
+        lobby.join(Controls::Wasd);
+    }
+    if keyboard.just_pressed(KeyCode::ControlRight) {
+        lobby.join(Controls::ArrowKeys);
+    }
+    if keyboard.just_pressed(KeyCode::T) {
+        lobby.teams = !lobby.teams;
+    }
+    for gamepad in gamepads.iter() {
+        if gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South)) {
+            lobby.join(Controls::Gamepad(gamepad));
+        }
+    }
+}
+
+fn update_lobby_text(lobby: Res<Lobby>, mut query: Query<&mut Text, With<LobbyText>>) {
+    if !lobby.is_changed() {
+        return;
+    }
+
+    for mut text in &mut query {
+        let value = &mut text.sections[0].value;
+        value.clear();
+
+        writeln!(value, "Press Space, Right Ctrl or (A) to join").unwrap();
+        writeln!(
+            value,
+            "Press T to toggle teams ({})",
+            if lobby.teams { "on" } else { "off" }
+        )
+        .unwrap();
+        for (index, player) in lobby.players.iter().enumerate() {
+            write!(
+                value,
+                "Player {}: {}",
+                index + 1,
+                player.controls.description()
+            )
+            .unwrap();
+            if lobby.teams {
+                write!(value, ", team {}", lobby.team(index).0 + 1).unwrap();
+            }
+            writeln!(value).unwrap();
+        }
+        if !lobby.players.is_empty() {
+            write!(value, "Press Enter or Start to play").unwrap();
+        }
+    }
+}
+
+fn start_game(
+    lobby: Res<Lobby>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if lobby.players.is_empty() {
+        return;
+    }
+
+    let start_pressed = keyboard.just_pressed(KeyCode::Return)
+        || gamepads.iter().any(|gamepad| {
+            gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Start))
+        });
+
+    if start_pressed {
+        next_state.set(GameState::Playing);
+    }
+}
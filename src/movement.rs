@@ -0,0 +1,520 @@
+use std::time::Duration;
+
+use bevy::math::vec2;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::audio::{PlaySound, Sound};
+use crate::components::facing::Facing;
+use crate::damage::{Dead, Invulnerable};
+use crate::platforms::{DroppingThrough, OneWayPlatform};
+use crate::player::{Player, Standing, TouchingWall};
+use crate::{GameState, PlayerAction, PHYSICS_STEP};
+
+pub struct MovementPlugin;
+
+/// This plugin turns player input into running, jumping, dashing and getting around walls,
+/// tuned by the `MovementConfig` resource
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MovementConfig>().add_systems(
+            Update,
+            (move_player, move_on_walls, dash)
+                .chain()
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// Tunes how players run, jump, dash and get around walls
+#[derive(Resource, Clone, Debug)]
+pub struct MovementConfig {
+    /// Top running speed in pixels per second
+    pub run_speed: f32,
+    /// How fast players speed up on the ground, in pixels per second squared
+    pub ground_acceleration: f32,
+    /// How fast players slow down on the ground when letting go, in pixels per second squared
+    pub ground_deceleration: f32,
+    /// How fast players speed up in the air, in pixels per second squared
+    pub air_acceleration: f32,
+    /// How fast players slow down in the air when letting go, in pixels per second squared
+    pub air_deceleration: f32,
+    /// How many times faster than accelerating players turn around
+    pub turn_multiplier: f32,
+    /// Upward speed in pixels per second at the start of a jump
+    pub jump_speed: f32,
+    /// Share of the upward speed that is kept when letting go of jump early
+    pub jump_cut: f32,
+    /// Seconds after running off a ledge during which players can still jump
+    pub coyote_time: f32,
+    /// Seconds a jump pressed in the air is remembered, to jump as soon as the player lands
+    pub jump_buffer: f32,
+    /// Fastest players slide down a wall they are pushing against, in pixels per second
+    pub wall_slide_speed: f32,
+    /// Velocity in pixels per second of a jump off a wall, pointing away from the wall
+    pub wall_jump_speed: Vec2,
+    /// Seconds after a wall jump before players can steer again
+    pub wall_jump_lock: f32,
+    /// Seconds it takes to climb up onto a ledge
+    pub climb_time: f32,
+    /// Speed in pixels per second of a dash
+    pub dash_speed: f32,
+    /// Seconds a dash lasts
+    pub dash_time: f32,
+    /// Seconds from the start of a dash until the next one
+    pub dash_cooldown: f32,
+    /// Dashes players can make before having to land again
+    pub air_dashes: u32,
+    /// Seconds from the start of a dash during which bullets pass through the player
+    pub dash_invulnerability: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            run_speed: 150.,
+            ground_acceleration: 1200.,
+            ground_deceleration: 1800.,
+            air_acceleration: 700.,
+            air_deceleration: 250.,
+            turn_multiplier: 2.,
+            jump_speed: 384.,
+            jump_cut: 0.5,
+            coyote_time: 0.1,
+            jump_buffer: 0.12,
+            wall_slide_speed: 60.,
+            wall_jump_speed: vec2(200., 360.),
+            wall_jump_lock: 0.15,
+            climb_time: 0.3,
+            dash_speed: 450.,
+            dash_time: 0.15,
+            dash_cooldown: 0.6,
+            air_dashes: 1,
+            dash_invulnerability: 0.2,
+        }
+    }
+}
+
+/// Keeps track of a player's jumps between frames
+#[derive(Component, Clone, Debug, Default)]
+pub struct Jumping {
+    /// Seconds since the player last stood on something
+    airborne_time: f32,
+    /// Seconds since jump was pressed, while the jump is waiting to happen
+    buffered: Option<f32>,
+    /// Whether the player is rising from a jump that can still be cut short
+    rising: bool,
+}
+
+impl Jumping {
+    /// Uses up the chance to jump until the player stands on something again
+    fn jump(&mut self) {
+        self.buffered = None;
+        self.rising = true;
+        self.airborne_time = f32::INFINITY;
+    }
+}
+
+/// What a player is doing against a wall, as seen by animation state machines
+#[derive(serde::Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WallMove {
+    Sliding,
+    Jumping,
+    Hanging,
+    Climbing,
+}
+
+/// Keeps track of a player sliding down, jumping off and climbing walls
+#[derive(Component, Clone, Debug, Default)]
+pub struct WallMovement {
+    state: WallState,
+}
+
+#[derive(Clone, Debug, Default)]
+enum WallState {
+    #[default]
+    Off,
+    Sliding,
+    /// Kicking away from a wall, out of the player's control until the timer finishes
+    Jumping(Timer),
+    /// Holding on to the ledge at the given height
+    Hanging(f32),
+    Climbing {
+        timer: Timer,
+        from: Vec2,
+        to: Vec2,
+    },
+}
+
+impl WallMovement {
+    pub fn current(&self) -> Option<WallMove> {
+        match self.state {
+            WallState::Off => None,
+            WallState::Sliding => Some(WallMove::Sliding),
+            WallState::Jumping(_) => Some(WallMove::Jumping),
+            WallState::Hanging(_) => Some(WallMove::Hanging),
+            WallState::Climbing { .. } => Some(WallMove::Climbing),
+        }
+    }
+
+    /// Whether the player is hanging from or climbing up a ledge, and can't run or jump
+    fn is_holding_on(&self) -> bool {
+        matches!(
+            self.state,
+            WallState::Hanging(_) | WallState::Climbing { .. }
+        )
+    }
+}
+
+/// Keeps track of a player's dashes
+#[derive(Component, Clone, Debug, Default)]
+pub struct Dash {
+    /// How long the dash underway has left, and which way it goes
+    active: Option<(Timer, Vec2)>,
+    /// Until the next dash, finished when left out
+    cooldown: Option<Timer>,
+    /// Dashes left before the player has to land again
+    air_dashes_left: u32,
+}
+
+/// Distance from the center of a player down from the ledge they are hanging from
+const HANG_DEPTH: f32 = 14.;
+
+/// Distance from the center of a player to the soles of their feet, when climbed up on a ledge
+const CLIMB_HEIGHT: f32 = 16.5;
+
+/// How far over the edge players get when climbing up on a ledge
+const CLIMB_OVER: f32 = 12.;
+
+/// Moves `current` towards `target` by at most `max_delta`
+fn approach(current: f32, target: f32, max_delta: f32) -> f32 {
+    current + (target - current).clamp(-max_delta, max_delta)
+}
+
+#[allow(clippy::type_complexity)]
+fn move_player(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Velocity,
+            &mut Jumping,
+            &WallMovement,
+            &ActionState<PlayerAction>,
+            &mut Facing,
+            &Standing,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+    one_way_query: Query<(), With<OneWayPlatform>>,
+    mut sound_events: EventWriter<PlaySound>,
+    config: Res<MovementConfig>,
+) {
+    for (
+        entity,
+        transform,
+        mut velocity,
+        mut jumping,
+        wall_movement,
+        action_state,
+        mut facing,
+        standing,
+    ) in &mut query
+    {
+        let axis_pair = action_state
+            .axis_pair(PlayerAction::Move)
+            .unwrap_or_default();
+
+        if standing.is_standing {
+            jumping.airborne_time = 0.;
+        } else {
+            jumping.airborne_time += PHYSICS_STEP;
+        }
+
+        if action_state.just_pressed(PlayerAction::Jump) {
+            jumping.buffered = Some(0.);
+        } else if let Some(waited) = jumping.buffered {
+            jumping.buffered = Some(waited + PHYSICS_STEP).filter(|&t| t <= config.jump_buffer);
+        }
+
+        // Ledges are let go of and climbed in `move_on_walls`
+        if wall_movement.is_holding_on() {
+            continue;
+        }
+
+        // Kicking off a wall doesn't leave room for steering
+        if !matches!(wall_movement.state, WallState::Jumping(_)) {
+            if axis_pair.x() > 0.1 {
+                facing.set(Facing::Right);
+            } else if axis_pair.x() < -0.1 {
+                facing.set(Facing::Left);
+            }
+
+            let target_speed = axis_pair.x() * config.run_speed;
+            let (acceleration, deceleration) = if standing.is_standing {
+                (config.ground_acceleration, config.ground_deceleration)
+            } else {
+                (config.air_acceleration, config.air_deceleration)
+            };
+            let turning = target_speed * velocity.linvel.x < 0.;
+            let rate = if turning {
+                acceleration * config.turn_multiplier
+            } else if target_speed.abs() < velocity.linvel.x.abs() {
+                deceleration
+            } else {
+                acceleration
+            };
+            velocity.linvel.x = approach(velocity.linvel.x, target_speed, rate * PHYSICS_STEP);
+        }
+
+        let on_one_way_platform = standing
+            .ground
+            .is_some_and(|ground| one_way_query.contains(ground));
+        let can_jump = jumping.airborne_time <= config.coyote_time;
+
+        if jumping.buffered.is_some() && on_one_way_platform && axis_pair.y() < -0.5 {
+            jumping.buffered = None;
+            commands.entity(entity).insert(DroppingThrough::default());
+        } else if jumping.buffered.is_some() && can_jump {
+            jumping.jump();
+            velocity.linvel.y = config.jump_speed;
+            sound_events.send(PlaySound::new(
+                Sound::Jump,
+                transform.translation.truncate(),
+            ));
+        } else if jumping.rising {
+            // Letting go of jump early makes for a shorter jump
+            if velocity.linvel.y <= 0. {
+                jumping.rising = false;
+            } else if !action_state.pressed(PlayerAction::Jump) {
+                velocity.linvel.y *= config.jump_cut;
+                jumping.rising = false;
+            }
+        }
+    }
+}
+
+/// Slides down walls, jumps off them, and grabs and climbs up ledges
+#[allow(clippy::type_complexity)]
+pub fn move_on_walls(
+    mut query: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut Jumping,
+            &mut WallMovement,
+            &mut Facing,
+            &TouchingWall,
+            &Standing,
+            &ActionState<PlayerAction>,
+            Has<Dead>,
+        ),
+        With<Player>,
+    >,
+    mut sound_events: EventWriter<PlaySound>,
+    config: Res<MovementConfig>,
+) {
+    let delta = Duration::from_secs_f32(PHYSICS_STEP);
+
+    for (
+        mut transform,
+        mut velocity,
+        mut jumping,
+        mut wall_movement,
+        mut facing,
+        touching,
+        standing,
+        action_state,
+        dead,
+    ) in &mut query
+    {
+        if dead {
+            wall_movement.state = WallState::Off;
+            continue;
+        }
+
+        let axis_pair = action_state
+            .axis_pair(PlayerAction::Move)
+            .unwrap_or_default();
+        let side = facing.direction();
+        let position = transform.translation.truncate();
+        let jump_off = |velocity: &mut Velocity, jumping: &mut Jumping, facing: &mut Facing| {
+            jumping.jump();
+            velocity.linvel = vec2(-side * config.wall_jump_speed.x, config.wall_jump_speed.y);
+            facing.set(if side > 0. {
+                Facing::Left
+            } else {
+                Facing::Right
+            });
+            WallState::Jumping(Timer::from_seconds(config.wall_jump_lock, TimerMode::Once))
+        };
+
+        match &mut wall_movement.state {
+            WallState::Climbing { timer, from, to } => {
+                // Straight up first, then over the edge
+                let progress = timer.tick(delta).percent();
+                let up = (progress * 2.).min(1.);
+                let over = (progress * 2. - 1.).max(0.);
+                transform.translation.x = from.x + (to.x - from.x) * over;
+                transform.translation.y = from.y + (to.y - from.y) * up;
+                *velocity = Velocity::zero();
+
+                if timer.finished() {
+                    wall_movement.state = WallState::Off;
+                }
+                continue;
+            }
+            WallState::Hanging(ledge) => {
+                let ledge = *ledge;
+
+                if touching.wall.is_none() || axis_pair.y() < -0.5 {
+                    wall_movement.state = WallState::Off;
+                } else if jumping.buffered.is_some() && axis_pair.x() * side < -0.1 {
+                    wall_movement.state = jump_off(&mut velocity, &mut jumping, &mut facing);
+                    sound_events.send(PlaySound::new(Sound::Jump, position));
+                } else if jumping.buffered.is_some() || axis_pair.y() > 0.5 {
+                    jumping.buffered = None;
+                    wall_movement.state = WallState::Climbing {
+                        timer: Timer::from_seconds(config.climb_time, TimerMode::Once),
+                        from: position,
+                        to: vec2(position.x + side * CLIMB_OVER, ledge + CLIMB_HEIGHT),
+                    };
+                    *velocity = Velocity::zero();
+                } else {
+                    transform.translation.y = ledge - HANG_DEPTH;
+                    *velocity = Velocity::zero();
+                }
+                continue;
+            }
+            WallState::Jumping(timer) => {
+                if !timer.tick(delta).finished() {
+                    continue;
+                }
+                wall_movement.state = WallState::Off;
+            }
+            WallState::Off | WallState::Sliding => {}
+        }
+
+        let airborne = !standing.is_standing;
+        let falling = velocity.linvel.y <= 0.;
+        let pushing_into_wall = axis_pair.x() * side > 0.1;
+
+        wall_movement.state = match (touching.wall, touching.ledge) {
+            (Some(_), Some(ledge)) if airborne && falling && pushing_into_wall => {
+                transform.translation.y = ledge - HANG_DEPTH;
+                *velocity = Velocity::zero();
+                WallState::Hanging(ledge)
+            }
+            (Some(_), _) if airborne && jumping.buffered.is_some() => {
+                sound_events.send(PlaySound::new(Sound::Jump, position));
+                jump_off(&mut velocity, &mut jumping, &mut facing)
+            }
+            (Some(_), _) if airborne && falling && pushing_into_wall => {
+                velocity.linvel.y = velocity.linvel.y.max(-config.wall_slide_speed);
+                WallState::Sliding
+            }
+            _ => WallState::Off,
+        };
+    }
+}
+
+/// Bursts players along the way they are pushing, or the way they face
+#[allow(clippy::type_complexity)]
+fn dash(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Velocity,
+            &mut Dash,
+            &WallMovement,
+            &Facing,
+            &Standing,
+            &ActionState<PlayerAction>,
+            Has<Dead>,
+        ),
+        With<Player>,
+    >,
+    mut sound_events: EventWriter<PlaySound>,
+    config: Res<MovementConfig>,
+) {
+    let delta = Duration::from_secs_f32(PHYSICS_STEP);
+
+    for (
+        entity,
+        transform,
+        mut velocity,
+        mut dash,
+        wall_movement,
+        facing,
+        standing,
+        action_state,
+        dead,
+    ) in &mut query
+    {
+        if dead {
+            dash.active = None;
+            continue;
+        }
+
+        if standing.is_standing {
+            dash.air_dashes_left = config.air_dashes;
+        }
+        if dash
+            .cooldown
+            .as_mut()
+            .is_some_and(|cooldown| cooldown.tick(delta).finished())
+        {
+            dash.cooldown = None;
+        }
+
+        if let Some((timer, direction)) = &mut dash.active {
+            if timer.tick(delta).finished() {
+                // Back to running speed, so the dash doesn't carry on as a slide;
+                // falling or rising carries on as it would have
+                velocity.linvel.x = direction.x * config.run_speed;
+                dash.active = None;
+            } else {
+                velocity.linvel = *direction * config.dash_speed;
+            }
+            continue;
+        }
+
+        let can_dash = dash.cooldown.is_none()
+            && (standing.is_standing || dash.air_dashes_left > 0)
+            && !wall_movement.is_holding_on();
+        if !action_state.just_pressed(PlayerAction::Dash) || !can_dash {
+            continue;
+        }
+
+        let axis_pair = action_state
+            .axis_pair(PlayerAction::Move)
+            .unwrap_or_default();
+        let direction = if axis_pair.xy().length() > 0.5 {
+            axis_pair.xy().normalize()
+        } else {
+            vec2(facing.direction(), 0.)
+        };
+
+        if !standing.is_standing {
+            dash.air_dashes_left -= 1;
+        }
+        dash.cooldown = Some(Timer::from_seconds(config.dash_cooldown, TimerMode::Once));
+        dash.active = Some((
+            Timer::from_seconds(config.dash_time, TimerMode::Once),
+            direction,
+        ));
+        velocity.linvel = direction * config.dash_speed;
+        sound_events.send(PlaySound::new(
+            Sound::Dash,
+            transform.translation.truncate(),
+        ));
+        commands
+            .entity(entity)
+            .insert(Invulnerable::from_seconds(config.dash_invulnerability));
+    }
+}
@@ -0,0 +1,419 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::view::NoFrustumCulling;
+use bevy::sprite::Mesh2dHandle;
+use bevy::transform::TransformSystem;
+use rand::Rng;
+
+use crate::world::WallGrid;
+
+pub struct ParticlesPlugin;
+
+/// This plugin simulates and draws lightweight particles for effects like blood, sparks and dust.
+/// Particles are plain data on their [ParticleEmitter] rather than entities or rigid bodies,
+/// and every emitter is drawn as a single mesh.
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticleMaterial>()
+            .add_event::<ParticleCollision>()
+            // After transform propagation, so emitters spawned this frame emit from the right place
+            .add_systems(
+                PostUpdate,
+                (
+                    prepare_emitters,
+                    emit_particles,
+                    update_particles,
+                    draw_particles,
+                    despawn_finished_emitters,
+                )
+                    .chain()
+                    .in_set(ParticleSystems)
+                    .after(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Simulates and draws the particles. Systems reading [ParticleCollision] in `PostUpdate`
+/// should run after it, while the emitters that sent them are still around.
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ParticleSystems;
+
+/// How the particles of an emitter are spawned and how they behave
+#[derive(Clone, Debug)]
+pub struct ParticleEffect {
+    /// Particles spawned per second while emitting
+    pub rate: f32,
+    /// Particles spawned all at once when the emitter starts
+    pub burst: u32,
+    /// Seconds to keep emitting at `rate`, or forever if `None`.
+    /// Emitters without a `rate` stop after their burst.
+    pub duration: Option<f32>,
+    /// Seconds each particle lives, picked at random within the range
+    pub lifetime: (f32, f32),
+    /// Initial speed in pixels per second, picked at random within the range
+    pub speed: (f32, f32),
+    /// Angle in degrees of the cone that particles are sent out within,
+    /// centered on the emitter's local x axis
+    pub spread: f32,
+    /// Acceleration in pixels per second squared
+    pub gravity: Vec2,
+    /// Fraction of their velocity particles lose per second
+    pub drag: f32,
+    /// Colors that particles fade through over their lifetime, evenly spaced
+    pub colors: Vec<Color>,
+    /// Sizes in pixels that particles grow or shrink through over their lifetime, evenly spaced
+    pub sizes: Vec<f32>,
+    /// What happens when a particle hits a wall tile, or `None` to pass through walls
+    pub collision: Option<CollisionResponse>,
+}
+
+impl Default for ParticleEffect {
+    fn default() -> Self {
+        Self {
+            rate: 0.,
+            burst: 0,
+            duration: None,
+            lifetime: (1., 1.),
+            speed: (0., 0.),
+            spread: 360.,
+            gravity: Vec2::ZERO,
+            drag: 0.,
+            colors: vec![Color::WHITE],
+            sizes: vec![1.],
+            collision: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CollisionResponse {
+    /// Bounce off, keeping this fraction of the speed, so 0 comes to a halt against the wall
+    Bounce(f32),
+    /// Disappear
+    Remove,
+}
+
+/// Sent whenever a particle of an emitter with collision enabled hits a wall tile
+#[derive(Event, Clone, Debug)]
+pub struct ParticleCollision {
+    pub emitter: Entity,
+    /// Where the particle met the surface of the wall
+    pub position: Vec2,
+    /// Points out of the wall that was hit
+    pub normal: Vec2,
+    pub color: Color,
+    pub size: f32,
+}
+
+#[derive(Clone, Debug)]
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+}
+
+impl Particle {
+    fn progress(&self) -> f32 {
+        (self.age / self.lifetime).clamp(0., 1.)
+    }
+}
+
+/// Spawns particles from where it is, and keeps them in world space so they are left behind when
+/// it moves. Place it with a [SpatialBundle], rotated to aim the particles.
+#[derive(Component, Clone, Debug)]
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    /// Whether new particles are spawned, existing particles live on either way
+    pub emitting: bool,
+    /// Despawn the emitter entity once it has stopped emitting and its particles are gone
+    pub despawn_when_done: bool,
+    particles: Vec<Particle>,
+    elapsed: f32,
+    unspawned: f32,
+    burst_spawned: bool,
+}
+
+impl ParticleEmitter {
+    pub fn new(effect: ParticleEffect) -> Self {
+        Self {
+            effect,
+            emitting: true,
+            despawn_when_done: false,
+            particles: Vec::new(),
+            elapsed: 0.,
+            unspawned: 0.,
+            burst_spawned: false,
+        }
+    }
+
+    /// An emitter for a single burst of particles, that cleans up after itself
+    pub fn one_shot(effect: ParticleEffect) -> Self {
+        Self {
+            despawn_when_done: true,
+            ..Self::new(effect)
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        !self.emitting && self.particles.is_empty()
+    }
+
+    fn spawn(&mut self, count: u32, transform: &GlobalTransform) {
+        let effect = &self.effect;
+        let mut rng = rand::thread_rng();
+        let half_spread = effect.spread.to_radians().min(2. * PI) / 2.;
+
+        self.particles.extend((0..count).map(|_| {
+            let angle = rng.gen_range(-half_spread..=half_spread);
+            let local_direction = Vec2::from_angle(angle).extend(0.);
+            let direction = transform.affine().transform_vector3(local_direction);
+            let speed = rng.gen_range(effect.speed.0..=effect.speed.1);
+
+            Particle {
+                position: transform.translation().truncate(),
+                velocity: direction.truncate().normalize_or_zero() * speed,
+                age: 0.,
+                lifetime: rng.gen_range(effect.lifetime.0..=effect.lifetime.1),
+            }
+        }));
+    }
+}
+
+/// Samples evenly spaced `keys` at `progress` from 0 to 1
+fn sample<T: Copy>(keys: &[T], progress: f32, lerp: impl Fn(T, T, f32) -> T) -> Option<T> {
+    let last = keys.len().checked_sub(1)?;
+    let position = progress * last as f32;
+    let index = (position.floor() as usize).min(last);
+    let next = (index + 1).min(last);
+    Some(lerp(keys[index], keys[next], position - index as f32))
+}
+
+fn lerp_color(from: Color, to: Color, amount: f32) -> Color {
+    let from = Vec4::from(from.as_rgba_f32());
+    let to = Vec4::from(to.as_rgba_f32());
+    let [red, green, blue, alpha] = from.lerp(to, amount).to_array();
+    Color::rgba(red, green, blue, alpha)
+}
+
+impl ParticleEffect {
+    fn color(&self, progress: f32) -> Color {
+        sample(&self.colors, progress, lerp_color).unwrap_or(Color::WHITE)
+    }
+
+    fn size(&self, progress: f32) -> f32 {
+        sample(&self.sizes, progress, |from, to, amount| {
+            from + (to - from) * amount
+        })
+        .unwrap_or(1.)
+    }
+}
+
+/// White, so particles keep the colors of their vertices
+#[derive(Resource)]
+struct ParticleMaterial(Handle<ColorMaterial>);
+
+impl FromWorld for ParticleMaterial {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        Self(materials.add(ColorMaterial::from(Color::WHITE)))
+    }
+}
+
+/// Gives new emitters a mesh of their own to draw their particles into
+fn prepare_emitters(
+    mut commands: Commands,
+    query: Query<Entity, Added<ParticleEmitter>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<ParticleMaterial>,
+) {
+    for entity in &query {
+        let mesh = Mesh::new(PrimitiveTopology::TriangleList)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, Vec::<[f32; 4]>::new())
+            .with_indices(Some(Indices::U32(Vec::new())));
+
+        commands.entity(entity).insert((
+            Mesh2dHandle(meshes.add(mesh)),
+            material.0.clone(),
+            // The mesh changes every frame, so its bounds can't be relied on
+            NoFrustumCulling,
+        ));
+    }
+}
+
+fn emit_particles(mut query: Query<(&mut ParticleEmitter, &GlobalTransform)>, time: Res<Time>) {
+    for (mut emitter, transform) in &mut query {
+        if !emitter.emitting {
+            continue;
+        }
+
+        if !emitter.burst_spawned {
+            emitter.burst_spawned = true;
+            let burst = emitter.effect.burst;
+            emitter.spawn(burst, transform);
+        }
+
+        emitter.elapsed += time.delta_seconds();
+        emitter.unspawned += emitter.effect.rate * time.delta_seconds();
+        let count = emitter.unspawned.floor();
+        emitter.unspawned -= count;
+        emitter.spawn(count as u32, transform);
+
+        let finished = match emitter.effect.duration {
+            Some(duration) => emitter.elapsed >= duration,
+            None => emitter.effect.rate <= 0.,
+        };
+        if finished {
+            emitter.emitting = false;
+        }
+    }
+}
+
+fn update_particles(
+    mut query: Query<(Entity, &mut ParticleEmitter)>,
+    mut collision_events: EventWriter<ParticleCollision>,
+    wall_grid: Res<WallGrid>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+
+    for (entity, mut emitter) in &mut query {
+        let ParticleEmitter {
+            effect, particles, ..
+        } = &mut *emitter;
+
+        particles.retain_mut(|particle| {
+            particle.age += delta;
+            if particle.age >= particle.lifetime {
+                return false;
+            }
+            particle.velocity += effect.gravity * delta;
+            particle.velocity *= (1. - effect.drag * delta).max(0.);
+            let previous = particle.position;
+            let next = previous + particle.velocity * delta;
+
+            let Some(response) = effect.collision.filter(|_| wall_grid.is_wall(next)) else {
+                particle.position = next;
+                return true;
+            };
+
+            // Whichever axis took the particle into the wall is the side that was hit
+            let hit_side = wall_grid.is_wall(Vec2::new(next.x, previous.y));
+            let hit_top_or_bottom = wall_grid.is_wall(Vec2::new(previous.x, next.y));
+            let normal = Vec2::new(
+                if hit_side || !hit_top_or_bottom {
+                    -particle.velocity.x.signum()
+                } else {
+                    0.
+                },
+                if hit_top_or_bottom || !hit_side {
+                    -particle.velocity.y.signum()
+                } else {
+                    0.
+                },
+            )
+            .normalize_or_zero();
+
+            let tile = wall_grid.tile_rect(wall_grid.grid_coords(next));
+            let mut contact = next;
+            if normal.x != 0. {
+                contact.x = if normal.x > 0. {
+                    tile.max.x
+                } else {
+                    tile.min.x
+                };
+            }
+            if normal.y != 0. {
+                contact.y = if normal.y > 0. {
+                    tile.max.y
+                } else {
+                    tile.min.y
+                };
+            }
+
+            let progress = particle.progress();
+            collision_events.send(ParticleCollision {
+                emitter: entity,
+                position: contact,
+                normal,
+                color: effect.color(progress),
+                size: effect.size(progress),
+            });
+
+            match response {
+                CollisionResponse::Bounce(restitution) => {
+                    if normal.x != 0. {
+                        particle.velocity.x = -particle.velocity.x;
+                    }
+                    if normal.y != 0. {
+                        particle.velocity.y = -particle.velocity.y;
+                    }
+                    particle.velocity *= restitution;
+                    particle.position = contact + normal * 0.01;
+                    true
+                }
+                CollisionResponse::Remove => false,
+            }
+        });
+    }
+}
+
+/// Rebuilds each emitter's mesh with a square per particle
+fn draw_particles(
+    query: Query<(&ParticleEmitter, &GlobalTransform, &Mesh2dHandle)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (emitter, transform, mesh_handle) in &query {
+        let Some(mesh) = meshes.get_mut(&mesh_handle.0) else {
+            continue;
+        };
+
+        // Particles live in world space, but the mesh is drawn relative to the emitter
+        let to_local = transform.affine().inverse();
+
+        let count = emitter.particles.len();
+        let mut positions = Vec::with_capacity(count * 4);
+        let mut colors = Vec::with_capacity(count * 4);
+        let mut indices = Vec::with_capacity(count * 6);
+
+        for particle in &emitter.particles {
+            let progress = particle.progress();
+            let half_size = emitter.effect.size(progress) / 2.;
+            let color = emitter.effect.color(progress).as_linear_rgba_f32();
+
+            let first = positions.len() as u32;
+            for corner in [
+                Vec2::new(-half_size, -half_size),
+                Vec2::new(half_size, -half_size),
+                Vec2::new(half_size, half_size),
+                Vec2::new(-half_size, half_size),
+            ] {
+                let world = (particle.position + corner).extend(0.);
+                positions.push(
+                    to_local
+                        .transform_point3(world)
+                        .truncate()
+                        .extend(0.)
+                        .to_array(),
+                );
+                colors.push(color);
+            }
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.set_indices(Some(Indices::U32(indices)));
+    }
+}
+
+fn despawn_finished_emitters(mut commands: Commands, query: Query<(Entity, &ParticleEmitter)>) {
+    for (entity, emitter) in &query {
+        if emitter.despawn_when_done && emitter.is_done() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::f32::consts::FRAC_PI_4;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::geometry::SolverFlags;
+
+use crate::damage::{DamageRules, Invulnerable};
+use crate::platforms::{DroppingThrough, OneWayPlatform};
+use crate::player::{Bullet, Lifetime, Team};
+
+/// Gameplay rules that decide which colliders touch each other.
+/// Only pairs where one of the colliders has [ActiveHooks] enabled are checked.
+#[derive(SystemParam)]
+pub struct GamePhysicsHooks<'w, 's> {
+    bullets: Query<'w, 's, (&'static Bullet, &'static Lifetime)>,
+    teams: Query<'w, 's, &'static Team>,
+    invulnerable: Query<'w, 's, (), With<Invulnerable>>,
+    one_way_platforms: Query<'w, 's, (), With<OneWayPlatform>>,
+    dropping_through: Query<'w, 's, (), With<DroppingThrough>>,
+    damage_rules: Res<'w, DamageRules>,
+}
+
+impl BevyPhysicsHooks for GamePhysicsHooks<'_, '_> {
+    fn filter_contact_pair(&self, context: PairFilterContextView) -> Option<SolverFlags> {
+        let first = context.collider1();
+        let second = context.collider2();
+
+        let bullet_hit = match (self.bullets.get(first), self.bullets.get(second)) {
+            (Ok((bullet, lifetime)), _) => Some((bullet, lifetime, second)),
+            (_, Ok((bullet, lifetime))) => Some((bullet, lifetime, first)),
+            _ => None,
+        };
+
+        if let Some((bullet, lifetime, target)) = bullet_hit {
+            if self.invulnerable.contains(target) {
+                return None;
+            }
+            if let Ok(team) = self.teams.get(target) {
+                if !self
+                    .damage_rules
+                    .can_hit(bullet, lifetime.timer.elapsed_secs(), target, team)
+                {
+                    return None;
+                }
+            }
+        }
+
+        let one_way_rider = if self.one_way_platforms.contains(first) {
+            Some(second)
+        } else if self.one_way_platforms.contains(second) {
+            Some(first)
+        } else {
+            None
+        };
+        if one_way_rider.is_some_and(|rider| self.dropping_through.contains(rider)) {
+            return None;
+        }
+
+        Some(SolverFlags::COMPUTE_IMPULSES)
+    }
+
+    fn modify_solver_contacts(&self, mut context: ContactModificationContextView) {
+        // One-way platforms only push things up, so anything below can pass through them
+        let allowed_normal = if self.one_way_platforms.contains(context.collider1()) {
+            Vec2::Y
+        } else if self.one_way_platforms.contains(context.collider2()) {
+            -Vec2::Y
+        } else {
+            return;
+        };
+
+        context.update_as_oneway_platform(&allowed_normal, FRAC_PI_4);
+    }
+}
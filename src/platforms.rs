@@ -1,6 +1,402 @@
+use bevy::math::vec2;
 use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_ldtk::utils::ldtk_pixel_coords_to_translation_pivoted;
+use bevy_rapier2d::prelude::*;
+
+use crate::player::Standing;
+use crate::{GameState, PHYSICS_STEP};
+
 pub struct PlatformsPlugin;
 
+/// This plugin brings the platforms placed as LDtk entities to life:
+/// `MovingPlatform`s carry whoever stands on them along a path,
+/// `OneWayPlatform`s can be jumped up through and dropped down through with down + jump,
+/// and `CrumblingPlatform`s fall a moment after being stood on, and come back later
 impl Plugin for PlatformsPlugin {
-    fn build(&self, _app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.register_ldtk_entity::<MovingPlatformBundle>("MovingPlatform")
+            .register_ldtk_entity::<OneWayPlatformBundle>("OneWayPlatform")
+            .register_ldtk_entity::<CrumblingPlatformBundle>("CrumblingPlatform")
+            .add_systems(
+                Update,
+                (
+                    move_platforms,
+                    carry_riders,
+                    crumble_platforms,
+                    stop_dropping_through,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    add_carried_velocity.before(PhysicsSet::SyncBackend),
+                    remove_carried_velocity.after(PhysicsSet::Writeback),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Speed in pixels per second of moving platforms that don't set their own
+const DEFAULT_PLATFORM_SPEED: f32 = 60.;
+
+/// How long a player falls through one-way platforms after dropping down
+const DROP_THROUGH_TIME: f32 = 0.3;
+
+/// How fast crumbled platforms speed up as they fall, in pixels per second squared
+const CRUMBLED_FALL_ACCELERATION: f32 = 600.;
+
+/// Collision group of one-way platforms, so queries and character controllers can leave them out
+pub const ONE_WAY_GROUP: Group = Group::GROUP_3;
+
+/// The collider and look of a platform, sized after the LDtk entity
+#[derive(Clone, Bundle)]
+pub struct PlatformBodyBundle {
+    rigid_body: RigidBody,
+    collider: Collider,
+    active_hooks: ActiveHooks,
+    collision_groups: CollisionGroups,
+    sprite: Sprite,
+    texture: Handle<Image>,
+}
+
+impl PlatformBodyBundle {
+    fn new(entity_instance: &EntityInstance, rigid_body: RigidBody, color: Color) -> Self {
+        let size = vec2(entity_instance.width as f32, entity_instance.height as f32);
+        Self {
+            rigid_body,
+            collider: Collider::cuboid(size.x / 2., size.y / 2.),
+            active_hooks: ActiveHooks::empty(),
+            collision_groups: CollisionGroups::default(),
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            texture: default(),
+        }
+    }
+}
+
+/// Travels back and forth along a path, set as the `path` points of the LDtk entity
+#[derive(Clone, Component, Debug, Default)]
+pub struct MovingPlatform {
+    /// Where the platform travels between, relative to the level, starting with where it was placed
+    pub path: Vec<Vec2>,
+    /// Speed in pixels per second
+    pub speed: f32,
+    /// Index of the point in the path the platform is headed for
+    target: usize,
+    /// Whether the platform is going forward along the path, as opposed to heading back
+    backwards: bool,
+}
+
+impl MovingPlatform {
+    /// Heads for the next point on the path, turning around at either end
+    fn advance(&mut self) {
+        let last = self.path.len().saturating_sub(1);
+        if self.target == last {
+            self.backwards = true;
+        } else if self.target == 0 {
+            self.backwards = false;
+        }
+
+        if self.backwards {
+            self.target = self.target.saturating_sub(1);
+        } else {
+            self.target = (self.target + 1).min(last);
+        }
+    }
+}
+
+impl LdtkEntity for MovingPlatform {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _: Option<&Handle<Image>>,
+        _: Option<&TilesetDefinition>,
+        _: &AssetServer,
+        _: &mut Assets<TextureAtlas>,
+    ) -> Self {
+        let level_height = layer_instance.c_hei * layer_instance.grid_size;
+        let size = IVec2::new(entity_instance.width, entity_instance.height);
+        let to_translation = |pixel_coords: IVec2| {
+            ldtk_pixel_coords_to_translation_pivoted(
+                pixel_coords,
+                level_height,
+                size,
+                entity_instance.pivot,
+            )
+        };
+
+        let mut path = vec![to_translation(entity_instance.px)];
+        if let Ok(points) = entity_instance.iter_points_field("path") {
+            // Points are the grid cells the platform's pivot moves through
+            path.extend(points.map(|point| {
+                let pixel_coords =
+                    (point.as_vec2() + entity_instance.pivot) * layer_instance.grid_size as f32;
+                to_translation(pixel_coords.as_ivec2())
+            }));
+        }
+
+        Self {
+            path,
+            speed: entity_instance
+                .get_float_field("speed")
+                .copied()
+                .unwrap_or(DEFAULT_PLATFORM_SPEED),
+            target: 0,
+            backwards: false,
+        }
+    }
+}
+
+#[derive(Clone, Bundle, LdtkEntity)]
+pub struct MovingPlatformBundle {
+    #[ldtk_entity]
+    platform: MovingPlatform,
+    #[with(moving_platform_body)]
+    body: PlatformBodyBundle,
+    velocity: Velocity,
+}
+
+fn moving_platform_body(entity_instance: &EntityInstance) -> PlatformBodyBundle {
+    PlatformBodyBundle::new(
+        entity_instance,
+        RigidBody::KinematicVelocityBased,
+        Color::rgb(0.35, 0.45, 0.6),
+    )
+}
+
+/// Can be jumped onto from below, and dropped through by holding down while jumping
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct OneWayPlatform;
+
+#[derive(Clone, Bundle, LdtkEntity)]
+pub struct OneWayPlatformBundle {
+    one_way_platform: OneWayPlatform,
+    #[with(one_way_platform_body)]
+    body: PlatformBodyBundle,
+}
+
+fn one_way_platform_body(entity_instance: &EntityInstance) -> PlatformBodyBundle {
+    PlatformBodyBundle {
+        // The physics hooks let through whatever comes from below
+        active_hooks: ActiveHooks::FILTER_CONTACT_PAIRS | ActiveHooks::MODIFY_SOLVER_CONTACTS,
+        collision_groups: CollisionGroups::new(ONE_WAY_GROUP, Group::ALL),
+        ..PlatformBodyBundle::new(
+            entity_instance,
+            RigidBody::Fixed,
+            Color::rgb(0.55, 0.45, 0.3),
+        )
+    }
+}
+
+/// How fast whoever has this is being carried along by a moving platform they stand on.
+/// Added to their own velocity only while the physics step runs, so gameplay code never sees it.
+#[derive(Component, Copy, Clone, Debug, Default)]
+pub struct Carried {
+    pub velocity: Vec2,
+}
+
+/// Present on players dropping down through one-way platforms
+#[derive(Component, Clone, Debug)]
+pub struct DroppingThrough {
+    pub timer: Timer,
+}
+
+impl Default for DroppingThrough {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(DROP_THROUGH_TIME, TimerMode::Once),
+        }
+    }
+}
+
+/// Falls a moment after being stood on, and is put back in place a while later.
+/// The delays can be set as the `delay` and `respawn` fields of the LDtk entity.
+#[derive(Clone, Component, Debug)]
+pub struct CrumblingPlatform {
+    /// Seconds from being stood on until falling
+    pub delay: f32,
+    /// Seconds from falling until the platform is back in place
+    pub respawn_delay: f32,
+    state: CrumbleState,
+}
+
+#[derive(Clone, Debug)]
+enum CrumbleState {
+    Intact,
+    Crumbling(Timer),
+    Fallen { timer: Timer, home: Vec3 },
+}
+
+impl From<&EntityInstance> for CrumblingPlatform {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Self {
+            delay: non_negative_field(entity_instance, "delay", 0.5),
+            respawn_delay: non_negative_field(entity_instance, "respawn", 3.),
+            state: CrumbleState::Intact,
+        }
+    }
+}
+
+/// Reads a float field that can't go below zero, like a delay, falling back to zero when it does
+fn non_negative_field(entity_instance: &EntityInstance, identifier: &str, default: f32) -> f32 {
+    let value = entity_instance
+        .get_float_field(identifier)
+        .copied()
+        .unwrap_or(default);
+    if value >= 0. {
+        value
+    } else {
+        warn!(
+            "{} {} has a negative {}, using 0",
+            entity_instance.identifier, entity_instance.iid, identifier
+        );
+        0.
+    }
+}
+
+#[derive(Clone, Bundle, LdtkEntity)]
+pub struct CrumblingPlatformBundle {
+    #[from_entity_instance]
+    platform: CrumblingPlatform,
+    #[with(crumbling_platform_body)]
+    body: PlatformBodyBundle,
+    velocity: Velocity,
+}
+
+fn crumbling_platform_body(entity_instance: &EntityInstance) -> PlatformBodyBundle {
+    PlatformBodyBundle::new(
+        entity_instance,
+        RigidBody::Fixed,
+        Color::rgb(0.6, 0.5, 0.45),
+    )
+}
+
+fn move_platforms(mut query: Query<(&mut MovingPlatform, &Transform, &mut Velocity)>) {
+    for (mut platform, transform, mut velocity) in &mut query {
+        if platform.path.len() < 2 {
+            continue;
+        }
+
+        let mut offset = platform.path[platform.target] - transform.translation.truncate();
+        if offset.length() <= platform.speed * PHYSICS_STEP {
+            platform.advance();
+            offset = platform.path[platform.target] - transform.translation.truncate();
+        }
+
+        // Slowing down on the last step, so the platform ends up exactly on the point
+        let speed = platform.speed.min(offset.length() / PHYSICS_STEP);
+        velocity.linvel = offset.normalize_or_zero() * speed;
+    }
+}
+
+/// Has whoever stands on a moving platform be carried along with it
+fn carry_riders(
+    mut rider_query: Query<(&mut Carried, &Standing)>,
+    platform_query: Query<&Velocity, With<MovingPlatform>>,
+) {
+    for (mut carried, standing) in &mut rider_query {
+        carried.velocity = standing
+            .ground
+            .and_then(|ground| platform_query.get(ground).ok())
+            .map_or(Vec2::ZERO, |velocity| velocity.linvel);
+    }
+}
+
+/// Dynamic riders are carried by the physics engine; kinematic ones by their character controller
+fn add_carried_velocity(
+    mut query: Query<(&mut Velocity, &Carried), Without<KinematicCharacterController>>,
+) {
+    for (mut velocity, carried) in &mut query {
+        velocity.linvel += carried.velocity;
+    }
+}
+
+fn remove_carried_velocity(
+    mut query: Query<(&mut Velocity, &Carried), Without<KinematicCharacterController>>,
+) {
+    for (mut velocity, carried) in &mut query {
+        velocity.linvel -= carried.velocity;
+    }
+}
+
+fn crumble_platforms(
+    mut commands: Commands,
+    mut platform_query: Query<(
+        Entity,
+        &mut CrumblingPlatform,
+        &mut Transform,
+        &mut RigidBody,
+        &mut Velocity,
+        &mut Sprite,
+    )>,
+    rider_query: Query<&Standing>,
+    time: Res<Time>,
+) {
+    let stood_on: HashSet<Entity> = rider_query
+        .iter()
+        .filter_map(|standing| standing.ground)
+        .collect();
+
+    for (entity, mut platform, mut transform, mut rigid_body, mut velocity, mut sprite) in
+        &mut platform_query
+    {
+        let delay = platform.delay;
+        let respawn_delay = platform.respawn_delay;
+
+        match &mut platform.state {
+            CrumbleState::Intact => {
+                if stood_on.contains(&entity) {
+                    platform.state =
+                        CrumbleState::Crumbling(Timer::from_seconds(delay, TimerMode::Once));
+                }
+            }
+            CrumbleState::Crumbling(timer) => {
+                timer.tick(time.delta());
+                // Fading a little to warn whoever is standing on it
+                sprite.color.set_a(1. - timer.percent() * 0.3);
+
+                if timer.finished() {
+                    *rigid_body = RigidBody::KinematicVelocityBased;
+                    commands.entity(entity).insert(ColliderDisabled);
+                    platform.state = CrumbleState::Fallen {
+                        timer: Timer::from_seconds(respawn_delay, TimerMode::Once),
+                        home: transform.translation,
+                    };
+                }
+            }
+            CrumbleState::Fallen { timer, home } => {
+                timer.tick(time.delta());
+                velocity.linvel.y -= CRUMBLED_FALL_ACCELERATION * time.delta_seconds();
+                sprite.color.set_a((0.7 - timer.percent() * 4.).max(0.));
+
+                if timer.finished() {
+                    transform.translation = *home;
+                    *rigid_body = RigidBody::Fixed;
+                    *velocity = Velocity::zero();
+                    sprite.color.set_a(1.);
+                    commands.entity(entity).remove::<ColliderDisabled>();
+                    platform.state = CrumbleState::Intact;
+                }
+            }
+        }
+    }
+}
+
+fn stop_dropping_through(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut DroppingThrough)>,
+    time: Res<Time>,
+) {
+    for (entity, mut dropping) in &mut query {
+        if dropping.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<DroppingThrough>();
+        }
+    }
 }
@@ -3,35 +3,63 @@ use std::time::Duration;
 use bevy::math::{vec2, vec3};
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
-use bevy::utils::hashbrown::HashMap;
 use bevy_rapier2d::prelude::*;
 use leafwing_input_manager::prelude::*;
+use rand::Rng;
 
-use crate::animation::{Animation, Clip};
+use crate::animation::{
+    Animation, AnimationContext, AnimationFrameEvent, AnimationSheet, DespawnOnAnimationEnd,
+};
+use crate::audio::{PlaySound, Sound};
 use crate::camera::CameraTarget;
+use crate::character::ControllerMode;
 use crate::components::aiming::{Aiming, AimingChild};
 use crate::components::facing::Facing;
-use crate::loading::TextureAssets;
+use crate::damage::Dead;
+use crate::impacts::BulletImpact;
+use crate::loading::{TextureAssets, WeaponAssets};
+use crate::lobby::Lobby;
+use crate::movement::{move_on_walls, Dash, Jumping, WallMovement};
+use crate::particles::{CollisionResponse, ParticleEffect, ParticleEmitter};
+use crate::platforms::{Carried, DroppingThrough, OneWayPlatform, ONE_WAY_GROUP};
+use crate::weapon::{Ammo, Arsenal, EmptyClick, Weapon, DEFAULT_WEAPON};
+use crate::world::{SpawnPoints, WallCollider};
 use crate::{GameState, PlayerAction, PHYSICS_STEP};
 
 pub struct PlayerPlugin;
 
-#[derive(Component, Default)]
-pub struct Player {}
+#[derive(Component)]
+pub struct Player {
+    /// The player's index in the lobby, in join order
+    pub id: usize,
+}
+
+/// Players on the same team are friendly to each other
+#[derive(Component, Reflect, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Team(pub usize);
 
 #[derive(Component)]
 pub struct Standing {
     pub is_standing: bool,
+    /// What is being stood on
+    pub ground: Option<Entity>,
+    /// Whether the ground has been touched since (re)spawning;
+    /// dropping into place at a spawn point doesn't count as landing
+    pub has_landed: bool,
 }
 
 #[derive(Component, Reflect, Default, Clone, Debug)]
 pub struct Health {
     pub health: f32,
+    pub max: f32,
 }
 
 impl Health {
     pub fn new(health: f32) -> Self {
-        Self { health }
+        Self {
+            health,
+            max: health,
+        }
     }
 }
 
@@ -40,13 +68,42 @@ pub struct Lifetime {
     pub timer: Timer,
 }
 
-#[derive(Component, Reflect, Default)]
+/// The weapon a player is holding, attached to the sprite in the player's hand
+#[derive(Component, Reflect, Clone, Debug)]
 pub struct Gun {
+    /// Name of the weapon in the arsenal
+    pub weapon_name: String,
+    pub weapon: Weapon,
     pub shot_timer: Timer,
 }
 
+impl Gun {
+    pub fn new(weapon_name: &str, weapon: &Weapon) -> Self {
+        Self {
+            weapon_name: String::from(weapon_name),
+            weapon: weapon.clone(),
+            shot_timer: Timer::from_seconds(1. / weapon.fire_rate, TimerMode::Once),
+        }
+    }
+}
+
+/// Sent when a gun fires a shot
+#[derive(Event, Clone, Debug)]
+pub struct GunFired {
+    pub gun: Entity,
+    /// Where the bullets leave the gun
+    pub muzzle: Vec2,
+}
+
 #[derive(Component, Reflect)]
-pub struct Bullet;
+pub struct Bullet {
+    pub damage: f32,
+    /// The player that fired the bullet
+    pub owner: Entity,
+    pub team: Team,
+    /// Wall bounces left before the bullet is destroyed
+    pub bounces_left: u32,
+}
 
 impl Lifetime {
     pub fn from_seconds(seconds: f32) -> Self {
@@ -56,9 +113,21 @@ impl Lifetime {
     }
 }
 
+/// A wall right next to a player, on the side they are facing
+#[derive(Component, Default)]
+pub struct TouchingWall {
+    pub wall: Option<Entity>,
+    /// Height of the top of the wall, when it is within reach of the player's hands
+    pub ledge: Option<f32>,
+}
+
 impl Default for Standing {
     fn default() -> Self {
-        Self { is_standing: false }
+        Self {
+            is_standing: false,
+            ground: None,
+            has_landed: false,
+        }
     }
 }
 
@@ -66,177 +135,226 @@ impl Default for Standing {
 /// Player logic is only active during the State `GameState::Playing`
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::Playing), spawn_player);
+        app.add_event::<GunFired>().add_systems(
+            Update,
+            spawn_player
+                .run_if(in_state(GameState::Playing))
+                .run_if(not(any_with_component::<Player>())),
+        );
         app.add_systems(
             Update,
             (
-                move_player,
                 animate_player,
                 change_aim,
                 rotate_aim_children,
                 check_if_standing,
+                check_walls.before(move_on_walls),
                 shoot,
+                muzzle_flash.after(shoot),
+                bullet_bounces,
+                kick_up_dust,
                 gun_time,
                 lifetime_cleanup,
-                health_remove_on_hit,
             )
                 .run_if(in_state(GameState::Playing)),
         );
     }
 }
 
-fn health_remove_on_hit(query: Query<&Health>) {
-    for health in &query {
-        if health.health <= 0. {
-            println!("Player died");
-        }
-    }
-}
+/// Share of a player's width that is felt for ground below, so standing on a ledge doesn't flicker
+const FOOTING_WIDTH: f32 = 0.75;
 
 fn check_if_standing(
-    mut query: Query<(&Transform, &Collider, &mut Standing)>,
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        &mut Standing,
+        Has<DroppingThrough>,
+    )>,
+    one_way_query: Query<(), With<OneWayPlatform>>,
+    mut sound_events: EventWriter<PlaySound>,
     rapier_context: Res<RapierContext>,
 ) {
-    for (transform, collider, mut standing) in &mut query {
-        let position = transform.translation;
-        let filter = QueryFilter::only_fixed();
-
-        let distance_down = collider.raw.compute_local_aabb().half_extents().y + 1.;
-
-        if let Some((_, _)) = rapier_context.cast_ray(
-            position.truncate(),
-            -transform.local_y().truncate(),
-            distance_down,
-            true,
-            filter,
-        ) {
-            standing.is_standing = true;
-        } else {
-            standing.is_standing = false;
+    for (entity, transform, collider, mut standing, dropping_through) in &mut query {
+        let position = transform.translation.truncate();
+        let ignore_one_way = |entity| !(dropping_through && one_way_query.contains(entity));
+        // Moving platforms are kinematic, so only other players and loose objects are left out.
+        // Players can be kinematic too, so their own collider has to be left out as well.
+        let filter = QueryFilter::exclude_dynamic()
+            .exclude_sensors()
+            .exclude_collider(entity)
+            .predicate(&ignore_one_way);
+
+        let half_extents = collider.raw.compute_local_aabb().half_extents();
+        let half_height = half_extents.y;
+        let distance_down = half_height + 1.;
+        let footing = half_extents.x * FOOTING_WIDTH;
+
+        // Feeling below the middle first, then below either side
+        let ground = [0., -footing, footing].into_iter().find_map(|offset| {
+            rapier_context
+                .cast_ray(
+                    position + vec2(offset, 0.),
+                    -transform.local_y().truncate(),
+                    distance_down,
+                    true,
+                    filter,
+                )
+                // Passing up through a one-way platform is not standing on it
+                .filter(|&(entity, toi)| !one_way_query.contains(entity) || toi >= half_height - 1.)
+                .map(|(entity, _)| entity)
+        });
+
+        if ground.is_some() && !standing.is_standing && standing.has_landed {
+            sound_events.send(PlaySound::new(Sound::Land, position));
         }
+        standing.is_standing = ground.is_some();
+        standing.has_landed |= standing.is_standing;
+        standing.ground = ground;
     }
 }
 
-fn spawn_player(mut commands: Commands, textures: Res<TextureAssets>) {
-    let mut input_map = InputMap::default();
-
-    input_map
-        .insert(KeyCode::J, PlayerAction::Shoot)
-        .insert(MouseButton::Left, PlayerAction::Shoot)
-        .insert(VirtualDPad::wasd(), PlayerAction::Move)
-        .insert(KeyCode::Space, PlayerAction::Jump);
-    commands
-        .spawn(SpriteSheetBundle {
-            sprite: TextureAtlasSprite {
-                anchor: Anchor::Custom(vec2(0., -0.125)),
-                ..default()
-            },
-            texture_atlas: textures.cyborg.clone(),
-            transform: Transform::from_xyz(480., 256., 3.),
-            ..default()
-        })
-        .with_children(|parent| {
-            parent
-                .spawn(SpriteBundle {
-                    texture: textures.hand.clone(),
-                    transform: Transform::from_xyz(4., 4., -0.1),
-                    ..default()
-                })
-                .insert(AimingChild)
-                .with_children(|parent| {
-                    parent
-                        .spawn(SpriteBundle {
-                            texture: textures.gun.clone(),
-                            transform: Transform::from_xyz(12., 2., 0.05),
-                            ..default()
-                        })
-                        .insert(Gun {
-                            shot_timer: Timer::from_seconds(0.1, TimerMode::Once),
-                        });
-                });
-        })
-        .insert(Animation::new(
-            0.12,
-            HashMap::from([
-                (
-                    String::from("idle"),
-                    Clip {
-                        frames: 0..3,
-                        repeat: true,
-                    },
-                ),
-                (
-                    String::from("jumping"),
-                    Clip {
-                        frames: 6..9,
-                        repeat: false,
-                    },
-                ),
-                (
-                    String::from("running"),
-                    Clip {
-                        frames: 12..17,
-                        repeat: true,
-                    },
-                ),
-                (
-                    String::from("walking"),
-                    Clip {
-                        frames: 24..29,
-                        repeat: true,
-                    },
-                ),
-            ]),
-        ))
-        .insert(ActiveEvents::COLLISION_EVENTS)
-        .insert(RigidBody::Dynamic)
-        .insert(LockedAxes::ROTATION_LOCKED)
-        .insert(Collider::capsule_y(8., 8.))
-        .insert(ColliderScale::Absolute(vec2(1., 1.)))
-        .insert(ColliderMassProperties::Mass(80.0))
-        .insert(Ccd { enabled: true })
-        .insert(Facing::Right)
-        .insert(CameraTarget::with_radius(100.))
-        .insert(Aiming::default())
-        .insert(Standing::default())
-        .insert(Player::default())
-        .insert(Health::new(100.))
-        .insert(Velocity::linear(vec2(0., 0.)))
-        .insert(InputManagerBundle::<PlayerAction> {
-            action_state: ActionState::default(),
-            input_map,
-        });
-}
+/// How far past their side a player feels for walls
+const WALL_REACH: f32 = 2.;
 
-fn move_player(
-    mut query: Query<
-        (
-            &mut Velocity,
-            &ActionState<PlayerAction>,
-            &mut Facing,
-            &Standing,
-        ),
-        With<Player>,
-    >,
-) {
-    let speed = 150.;
+/// How far below the top of a player a ledge can be grabbed
+const LEDGE_REACH: f32 = 8.;
 
-    for (mut velocity, action_state, mut facing, standing) in &mut query {
-        let axis_pair = action_state
-            .axis_pair(PlayerAction::Move)
-            .unwrap_or_default();
+fn check_walls(
+    mut query: Query<(&Transform, &Collider, &Facing, &mut TouchingWall)>,
+    one_way_query: Query<(), With<OneWayPlatform>>,
+    rapier_context: Res<RapierContext>,
+) {
+    for (transform, collider, facing, mut touching) in &mut query {
+        let position = transform.translation.truncate();
+        let not_one_way = |entity| !one_way_query.contains(entity);
+        // Only the level itself can be climbed, not moving platforms or other players
+        let filter = QueryFilter::only_fixed()
+            .exclude_sensors()
+            .predicate(&not_one_way);
+
+        let half_extents = collider.raw.compute_local_aabb().half_extents();
+        let side = vec2(facing.direction(), 0.);
+        let reach = half_extents.x + WALL_REACH;
+        let top = position + vec2(0., half_extents.y);
+
+        let wall = rapier_context
+            .cast_ray(position, side, reach, true, filter)
+            .map(|(entity, _)| entity);
+        // A ledge is where the wall ends between the middle and the top of the player
+        let ledge = wall
+            .filter(|_| {
+                rapier_context
+                    .cast_ray(top, side, reach, true, filter)
+                    .is_none()
+            })
+            .and_then(|_| {
+                rapier_context.cast_ray(top + side * reach, Vec2::NEG_Y, LEDGE_REACH, true, filter)
+            })
+            .filter(|&(_, toi)| toi > 0.)
+            .map(|(_, toi)| top.y - toi);
+
+        touching.wall = wall;
+        touching.ledge = ledge;
+    }
+}
 
-        if axis_pair.x() > 0.1 {
-            facing.set(Facing::Right);
-        } else if axis_pair.x() < -0.1 {
-            facing.set(Facing::Left);
-        }
+/// How far above a spawn point's center the player is placed, so the capsule starts on the ground
+pub const SPAWN_HEIGHT_OFFSET: f32 = 8.;
 
-        velocity.linvel.x = axis_pair.x() * speed;
+fn spawn_player(
+    mut commands: Commands,
+    textures: Res<TextureAssets>,
+    weapon_assets: Res<WeaponAssets>,
+    animation_sheets: Res<Assets<AnimationSheet>>,
+    arsenals: Res<Assets<Arsenal>>,
+    spawn_points: Res<SpawnPoints>,
+    lobby: Res<Lobby>,
+) {
+    let cyborg = animation_sheets
+        .get(&textures.cyborg)
+        .expect("Animation sheets should be loaded before playing");
+    let arsenal = arsenals
+        .get(&weapon_assets.arsenal)
+        .expect("Arsenal should be loaded before playing");
+    let weapon = arsenal
+        .weapons
+        .get(DEFAULT_WEAPON)
+        .expect("Arsenal should contain the default weapon");
+
+    // The level spawns asynchronously, so wait until its spawn points are known
+    if spawn_points.positions.is_empty() {
+        return;
+    }
 
-        if action_state.just_pressed(PlayerAction::Jump) && standing.is_standing {
-            velocity.linvel.y = 384.;
+    for (id, lobby_player) in lobby.players.iter().enumerate() {
+        let Some(spawn_point) = spawn_points.get(id) else {
+            continue;
         };
+
+        commands
+            .spawn(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    anchor: Anchor::Custom(vec2(0., -0.125)),
+                    color: lobby_player.tint,
+                    ..default()
+                },
+                texture_atlas: cyborg.atlas.clone(),
+                transform: Transform::from_xyz(
+                    spawn_point.x,
+                    spawn_point.y + SPAWN_HEIGHT_OFFSET,
+                    3.,
+                ),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn(SpriteBundle {
+                        texture: textures.hand.clone(),
+                        transform: Transform::from_xyz(4., 4., -0.1),
+                        ..default()
+                    })
+                    .insert(AimingChild)
+                    .with_children(|parent| {
+                        parent
+                            .spawn(SpriteSheetBundle {
+                                sprite: TextureAtlasSprite::new(weapon.sprite),
+                                texture_atlas: arsenal.atlas.clone(),
+                                transform: Transform::from_xyz(12., 2., 0.05),
+                                ..default()
+                            })
+                            .insert(Gun::new(DEFAULT_WEAPON, weapon));
+                    });
+            })
+            .insert(cyborg.animation())
+            .insert(textures.cyborg.clone())
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            .insert(RigidBody::Dynamic)
+            .insert(ControllerMode::default())
+            .insert(LockedAxes::ROTATION_LOCKED)
+            .insert(Collider::capsule_y(8., 8.))
+            .insert(ColliderScale::Absolute(vec2(1., 1.)))
+            .insert(ColliderMassProperties::Mass(80.0))
+            .insert(Ccd { enabled: true })
+            .insert(Facing::Right)
+            .insert(CameraTarget::with_radius(100.))
+            .insert(Aiming::default())
+            .insert(Standing::default())
+            .insert(Carried::default())
+            .insert(Jumping::default())
+            .insert(TouchingWall::default())
+            .insert(WallMovement::default())
+            .insert(Dash::default())
+            .insert(Player { id })
+            .insert(lobby.team(id))
+            .insert(Health::new(100.))
+            .insert(Ammo::new(weapon))
+            .insert(Velocity::linear(vec2(0., 0.)))
+            .insert(InputManagerBundle::<PlayerAction> {
+                action_state: ActionState::default(),
+                input_map: lobby_player.controls.input_map(),
+            });
     }
 }
 
@@ -248,21 +366,43 @@ fn gun_time(mut query: Query<&mut Gun>) {
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn shoot(
     mut commands: Commands,
-    query: Query<(&Children, &Velocity, &ActionState<PlayerAction>), With<Player>>,
+    mut query: Query<
+        (
+            Entity,
+            &Team,
+            &Children,
+            &Velocity,
+            &mut Ammo,
+            &ActionState<PlayerAction>,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
     arm_query: Query<&Children, With<AimingChild>>,
-    mut gun_query: Query<(&GlobalTransform, &mut Gun)>,
+    mut gun_query: Query<(Entity, &GlobalTransform, &mut Gun)>,
+    mut empty_click_events: EventWriter<EmptyClick>,
+    mut gun_fired_events: EventWriter<GunFired>,
     textures: Res<TextureAssets>,
 ) {
-    let bullet_speed = 400.;
-    for (children, velocity, action_state) in &query {
-        if action_state.pressed(PlayerAction::Shoot) {
-            let arm_children = arm_query.get(children[0]).unwrap();
-            let (gun_transform, mut gun) = gun_query.get_mut(arm_children[0]).unwrap();
+    let mut rng = rand::thread_rng();
 
-            if gun.shot_timer.finished() {
+    for (player, team, children, velocity, mut ammo, action_state) in &mut query {
+        if action_state.pressed(PlayerAction::Shoot) && !ammo.is_reloading() {
+            let arm_children = arm_query.get(children[0]).unwrap();
+            let (gun_entity, gun_transform, mut gun) = gun_query.get_mut(arm_children[0]).unwrap();
+
+            if ammo.loaded == 0 {
+                // Holding the trigger on an empty gun only clicks once
+                if action_state.just_pressed(PlayerAction::Shoot) {
+                    empty_click_events.send(EmptyClick { player });
+                }
+            } else if gun.shot_timer.finished() {
                 gun.shot_timer.reset();
+                ammo.loaded -= 1;
+
+                let weapon = &gun.weapon;
 
                 let transform = gun_transform
                     .compute_transform()
@@ -274,37 +414,200 @@ fn shoot(
                     .normalize()
                     .truncate();
 
-                commands
-                    .spawn(SpriteBundle {
-                        texture: textures.bullet.clone(),
-                        transform: Transform::from_translation(transform.translation)
-                            .with_scale(Vec3::splat(2.)),
+                gun_fired_events.send(GunFired {
+                    gun: gun_entity,
+                    muzzle: transform.translation.truncate(),
+                });
+
+                let half_spread = weapon.spread.to_radians() / 2.;
+                for _ in 0..weapon.pellets {
+                    let angle = if half_spread > 0. {
+                        rng.gen_range(-half_spread..=half_spread)
+                    } else {
+                        0.
+                    };
+                    let direction = Vec2::from_angle(angle).rotate(forward);
+
+                    commands
+                        .spawn(SpriteBundle {
+                            texture: textures.bullet.clone(),
+                            transform: Transform::from_translation(transform.translation)
+                                .with_scale(Vec3::splat(2.)),
+                            ..default()
+                        })
+                        .insert(Bullet {
+                            damage: weapon.damage,
+                            owner: player,
+                            team: *team,
+                            bounces_left: weapon.bounces,
+                        })
+                        .insert(ActiveEvents::COLLISION_EVENTS)
+                        .insert(ActiveHooks::FILTER_CONTACT_PAIRS)
+                        .insert(RigidBody::Dynamic)
+                        .insert(Collider::ball(BULLET_RADIUS))
+                        .insert(LockedAxes::ROTATION_LOCKED)
+                        .insert(CollisionGroups::new(
+                            Group::GROUP_2,
+                            Group::GROUP_1 | ONE_WAY_GROUP,
+                        ))
+                        .insert(Ccd { enabled: true })
+                        .insert(Restitution {
+                            coefficient: 1.,
+                            combine_rule: CoefficientCombineRule::Max,
+                        })
+                        .insert(Friction {
+                            coefficient: 0.,
+                            combine_rule: CoefficientCombineRule::Min,
+                        })
+                        .insert(GravityScale(0.))
+                        .insert(Lifetime::from_seconds(weapon.lifetime))
+                        .insert(Velocity::linear(
+                            direction * weapon.bullet_speed + velocity.linvel * 0.5,
+                        ));
+                }
+            }
+        }
+    }
+}
+
+/// Flashes the muzzle and plays the sound of every shot
+fn muzzle_flash(
+    mut commands: Commands,
+    mut gun_fired_events: EventReader<GunFired>,
+    mut sound_events: EventWriter<PlaySound>,
+    textures: Res<TextureAssets>,
+    animation_sheets: Res<Assets<AnimationSheet>>,
+) {
+    let shoot_effect = animation_sheets
+        .get(&textures.shoot_effect)
+        .expect("Animation sheets should be loaded before playing");
+
+    for gun_fired in gun_fired_events.read() {
+        sound_events.send(PlaySound::new(Sound::Shoot, gun_fired.muzzle));
+
+        let Some(mut gun) = commands.get_entity(gun_fired.gun) else {
+            continue;
+        };
+        let mut flash_animation = shoot_effect.animation();
+        flash_animation.play("shoot");
+        gun.with_children(|parent| {
+            parent
+                .spawn(SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        anchor: Anchor::CenterLeft,
                         ..default()
+                    },
+                    texture_atlas: shoot_effect.atlas.clone(),
+                    transform: Transform::from_xyz(6., 0., 0.1),
+                    ..default()
+                })
+                .insert(flash_animation)
+                .insert(DespawnOnAnimationEnd);
+        });
+    }
+}
+
+const BULLET_RADIUS: f32 = 1.;
+
+/// Destroys bullets that hit a wall after running out of bounces
+fn bullet_bounces(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut bullet_query: Query<(&mut Bullet, &Transform, &Velocity)>,
+    wall_query: Query<(), With<WallCollider>>,
+    mut sound_events: EventWriter<PlaySound>,
+    mut impact_events: EventWriter<BulletImpact>,
+    rapier_context: Res<RapierContext>,
+) {
+    for collision_event in collision_events.read() {
+        if let CollisionEvent::Started(first, second, _) = collision_event {
+            let (bullet, wall) = if wall_query.contains(*second) {
+                (*first, *second)
+            } else if wall_query.contains(*first) {
+                (*second, *first)
+            } else {
+                continue;
+            };
+            let Ok((mut bullet_component, transform, velocity)) = bullet_query.get_mut(bullet)
+            else {
+                continue;
+            };
+
+            let position = transform.translation.truncate();
+            sound_events.send(PlaySound::new(Sound::Ricochet, position));
+
+            // The contact normal points from the pair's first collider to the second
+            let normal = rapier_context
+                .contact_pair(bullet, wall)
+                .and_then(|contact_pair| {
+                    let normal = contact_pair.manifolds().next()?.normal();
+                    Some(if contact_pair.collider1() == wall {
+                        normal
+                    } else {
+                        -normal
                     })
-                    .insert(Bullet)
-                    .insert(RigidBody::Dynamic)
-                    .insert(Collider::ball(1.))
-                    .insert(LockedAxes::ROTATION_LOCKED)
-                    .insert(CollisionGroups::new(Group::GROUP_2, Group::GROUP_1))
-                    .insert(Ccd { enabled: true })
-                    .insert(Restitution {
-                        coefficient: 1.,
-                        combine_rule: CoefficientCombineRule::Max,
-                    })
-                    .insert(Friction {
-                        coefficient: 0.,
-                        combine_rule: CoefficientCombineRule::Min,
-                    })
-                    .insert(GravityScale(0.))
-                    .insert(Lifetime::from_seconds(2.0))
-                    .insert(Velocity::linear(
-                        forward * bullet_speed + velocity.linvel * 0.5,
-                    ));
+                })
+                // By now the bullet has already bounced, so it is headed away from the wall
+                .unwrap_or_else(|| velocity.linvel.normalize_or_zero());
+            impact_events.send(BulletImpact {
+                position: position - normal * BULLET_RADIUS,
+                normal,
+            });
+
+            if bullet_component.bounces_left == 0 {
+                commands.entity(bullet).despawn();
+            } else {
+                bullet_component.bounces_left -= 1;
             }
         }
     }
 }
 
+/// Distance from the center of a player to the soles of their feet
+const FOOT_OFFSET: f32 = 16.;
+
+/// Kicks up a puff of dust on the frames where a foot hits the ground
+fn kick_up_dust(
+    mut commands: Commands,
+    mut frame_events: EventReader<AnimationFrameEvent>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    for frame_event in frame_events.read() {
+        if frame_event.name != "footstep" {
+            continue;
+        }
+        let Ok(transform) = player_query.get(frame_event.entity) else {
+            continue;
+        };
+
+        let effect = ParticleEffect {
+            burst: 4,
+            lifetime: (0.15, 0.3),
+            speed: (10., 25.),
+            // Puffing out sideways along the ground
+            spread: 360.,
+            gravity: vec2(0., 20.),
+            drag: 4.,
+            colors: vec![
+                Color::rgba(0.8, 0.8, 0.8, 0.6),
+                Color::rgba(0.8, 0.8, 0.8, 0.),
+            ],
+            sizes: vec![2., 3.],
+            collision: Some(CollisionResponse::Bounce(0.)),
+            ..default()
+        };
+
+        commands.spawn((
+            ParticleEmitter::one_shot(effect),
+            SpatialBundle::from_transform(Transform::from_xyz(
+                transform.translation.x,
+                transform.translation.y - FOOT_OFFSET,
+                3.1,
+            )),
+        ));
+    }
+}
+
 fn lifetime_cleanup(mut commands: Commands, mut query: Query<(Entity, &mut Lifetime)>) {
     for (entity, mut lifetime) in &mut query {
         lifetime.timer.tick(Duration::from_secs_f32(PHYSICS_STEP));
@@ -316,7 +619,7 @@ fn lifetime_cleanup(mut commands: Commands, mut query: Query<(Entity, &mut Lifet
 
 const AIMING_SPEED: f32 = 2.;
 
-fn change_aim(mut query: Query<(&mut Aiming, &ActionState<PlayerAction>)>) {
+fn change_aim(mut query: Query<(&mut Aiming, &ActionState<PlayerAction>), Without<Dead>>) {
     for (mut aiming, action_state) in &mut query {
         let axis_pair = action_state
             .axis_pair(PlayerAction::Move)
@@ -342,26 +645,25 @@ fn rotate_aim_children(
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn animate_player(
-    mut query: Query<(&mut Animation, &ActionState<PlayerAction>, &Standing), With<Player>>,
+    mut query: Query<
+        (
+            &mut Animation,
+            &Velocity,
+            &Standing,
+            &WallMovement,
+            &ActionState<PlayerAction>,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
 ) {
-    for (mut animation, action_state, standing) in &mut query {
-        if standing.is_standing {
-            if action_state
-                .axis_pair(PlayerAction::Move)
-                .unwrap_or_default()
-                .x()
-                != 0.
-                && animation.current_animation != Some(String::from("running"))
-            {
-                animation.play("running", true)
-            } else if action_state.just_released(PlayerAction::Move)
-                || animation.current_animation == Some(String::from("jumping"))
-            {
-                animation.play("idle", true)
-            }
-        } else if animation.current_animation != Some(String::from("jumping")) {
-            animation.play("jumping", false)
-        }
+    for (mut animation, velocity, standing, wall_movement, action_state) in &mut query {
+        animation.update_state(&AnimationContext {
+            grounded: standing.is_standing,
+            velocity: velocity.linvel,
+            wall: wall_movement.current(),
+            actions: Some(action_state),
+        });
     }
 }
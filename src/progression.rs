@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::blood::Blood;
+use crate::particles::ParticleEmitter;
+use crate::player::{Bullet, Lifetime, Player, Standing, SPAWN_HEIGHT_OFFSET};
+use crate::world::SpawnPoints;
+use crate::GameState;
+
+pub struct ProgressionPlugin;
+
+/// This plugin moves the game through the levels of the LDtk project,
+/// either in the order they are listed or when a player reaches an `Exit`
+impl Plugin for ProgressionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelProgression>()
+            .add_event::<AdvanceLevel>()
+            .register_ldtk_entity::<ExitBundle>("Exit")
+            .add_systems(
+                Update,
+                (enter_exit, start_level_transition)
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                OnEnter(GameState::LevelTransition),
+                (clear_level_effects, pause_physics, begin_level_transition),
+            )
+            .add_systems(OnExit(GameState::LevelTransition), resume_physics)
+            .add_systems(
+                Update,
+                // Finishing is checked before the spawn is recorded, so the spawn points
+                // of the new level have been collected by the time the players are placed
+                (finish_level_transition, wait_for_level_spawn)
+                    .chain()
+                    .run_if(in_state(GameState::LevelTransition)),
+            );
+    }
+}
+
+/// Tracks which level is being played
+#[derive(Resource, Default, Debug)]
+pub struct LevelProgression {
+    /// Index of the current level, in the order the levels are listed in the LDtk project
+    pub current: usize,
+    /// The level being transitioned to
+    target: Option<LevelIid>,
+    /// Whether the target level has spawned
+    spawned: bool,
+}
+
+/// Send this event to move on to the next level
+#[derive(Event, Default)]
+pub struct AdvanceLevel;
+
+/// A trigger area that moves the players on to the next level when touched
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Exit;
+
+#[derive(Clone, Bundle, LdtkEntity)]
+pub struct ExitBundle {
+    exit: Exit,
+    #[from_entity_instance]
+    sensor: ExitSensorBundle,
+}
+
+#[derive(Clone, Bundle)]
+pub struct ExitSensorBundle {
+    collider: Collider,
+    sensor: Sensor,
+}
+
+impl From<&EntityInstance> for ExitSensorBundle {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Self {
+            collider: Collider::cuboid(
+                entity_instance.width as f32 / 2.,
+                entity_instance.height as f32 / 2.,
+            ),
+            sensor: Sensor,
+        }
+    }
+}
+
+fn enter_exit(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut advance_events: EventWriter<AdvanceLevel>,
+    exit_query: Query<&Exit>,
+    player_query: Query<&Player>,
+) {
+    for collision_event in collision_events.read() {
+        if let CollisionEvent::Started(first, second, _) = collision_event {
+            let exit = exit_query.get(*first).or(exit_query.get(*second));
+            let player = player_query.get(*first).or(player_query.get(*second));
+            if let (Ok(_), Ok(_)) = (exit, player) {
+                advance_events.send_default();
+            }
+        }
+    }
+}
+
+fn start_level_transition(
+    mut advance_events: EventReader<AdvanceLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if advance_events.read().count() > 0 {
+        next_state.set(GameState::LevelTransition);
+    }
+}
+
+/// Bullets, blood and particles belong to the level they were spawned in
+#[allow(clippy::type_complexity)]
+fn clear_level_effects(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        Or<(
+            With<Bullet>,
+            With<Blood>,
+            With<Lifetime>,
+            With<ParticleEmitter>,
+        )>,
+    >,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Keep players where they are until the next level is ready
+fn pause_physics(mut rapier_configuration: ResMut<RapierConfiguration>) {
+    rapier_configuration.physics_pipeline_active = false;
+}
+
+fn resume_physics(mut rapier_configuration: ResMut<RapierConfiguration>) {
+    rapier_configuration.physics_pipeline_active = true;
+}
+
+fn begin_level_transition(
+    mut commands: Commands,
+    mut progression: ResMut<LevelProgression>,
+    mut level_selection: ResMut<LevelSelection>,
+    level_query: Query<&LevelIid>,
+    ldtk_projects: Query<(Entity, &Handle<LdtkProject>)>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    let (ldtk_world, ldtk_handle) = ldtk_projects.single();
+    let ldtk_project = ldtk_project_assets
+        .get(ldtk_handle)
+        .expect("Project should be loaded while playing");
+
+    let level_count = ldtk_project.iter_raw_levels().count();
+    progression.current = (progression.current + 1) % level_count;
+
+    let next_level = ldtk_project
+        .iter_raw_levels()
+        .nth(progression.current)
+        .expect("Level index should be within the LDtk project");
+    let next_level_iid = LevelIid::new(next_level.iid.clone());
+
+    // Selecting a level that is already loaded does nothing, so respawn it instead
+    if level_query
+        .iter()
+        .any(|level_iid| *level_iid == next_level_iid)
+    {
+        commands.entity(ldtk_world).insert(Respawn);
+    }
+
+    *level_selection = LevelSelection::Iid(next_level_iid.clone());
+    progression.target = Some(next_level_iid);
+    progression.spawned = false;
+}
+
+fn wait_for_level_spawn(
+    mut level_events: EventReader<LevelEvent>,
+    mut progression: ResMut<LevelProgression>,
+    mut spawn_points: ResMut<SpawnPoints>,
+) {
+    for level_event in level_events.read() {
+        if let LevelEvent::Spawned(level_iid) = level_event {
+            if progression.target.as_ref() == Some(level_iid) {
+                progression.spawned = true;
+                // The previous level is gone by now, so the spawn points collected
+                // from here on are the new level's
+                *spawn_points = SpawnPoints::default();
+            }
+        }
+    }
+}
+
+/// Carries the players over to the spawn points of the new level
+fn finish_level_transition(
+    progression: Res<LevelProgression>,
+    spawn_points: Res<SpawnPoints>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut player_query: Query<(&Player, &mut Transform, &mut Velocity, &mut Standing)>,
+) {
+    if !progression.spawned || spawn_points.positions.is_empty() {
+        return;
+    }
+
+    for (player, mut transform, mut velocity, mut standing) in &mut player_query {
+        if let Some(spawn_point) = spawn_points.get(player.id) {
+            transform.translation.x = spawn_point.x;
+            transform.translation.y = spawn_point.y + SPAWN_HEIGHT_OFFSET;
+            *velocity = Velocity::zero();
+            *standing = Standing::default();
+        }
+    }
+
+    next_state.set(GameState::Playing);
+}
@@ -0,0 +1,445 @@
+use std::time::Duration;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use leafwing_input_manager::prelude::*;
+
+use crate::components::aiming::AimingChild;
+use crate::damage::Dead;
+use crate::loading::WeaponAssets;
+use crate::player::Gun;
+use crate::{GameState, PlayerAction, PHYSICS_STEP};
+
+pub struct WeaponPlugin;
+
+/// The weapon every player starts out with
+pub const DEFAULT_WEAPON: &str = "pistol";
+
+/// This plugin loads weapon definitions and equips them on the players' guns
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Arsenal>()
+            .init_asset_loader::<ArsenalLoader>()
+            .add_event::<EquipWeapon>()
+            .add_event::<EmptyClick>()
+            .add_systems(Update, reload_guns.run_if(in_state(GameState::Playing)))
+            .add_systems(
+                Update,
+                (equip_weapons, reload_arsenal).run_if(resource_exists::<WeaponAssets>()),
+            );
+    }
+}
+
+/// How a weapon fires, as defined in an arsenal descriptor
+#[derive(serde::Deserialize, Reflect, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Weapon {
+    /// Shots per second while the trigger is held
+    pub fire_rate: f32,
+    /// Angle in degrees of the cone that pellets are scattered within
+    #[serde(default)]
+    pub spread: f32,
+    /// Bullets fired per shot
+    #[serde(default = "default_pellets")]
+    pub pellets: u32,
+    pub bullet_speed: f32,
+    /// How many times a bullet can bounce off walls before it is destroyed
+    pub bounces: u32,
+    /// Seconds before a bullet disappears on its own
+    pub lifetime: f32,
+    /// Damage dealt by each bullet
+    pub damage: f32,
+    /// Shots that can be fired before reloading
+    pub magazine: u32,
+    /// Spare rounds a player carries for reloading, unlimited if left out
+    #[serde(default)]
+    pub reserve: Option<u32>,
+    /// Seconds it takes to reload
+    pub reload_time: f32,
+    /// Index of the weapon's sprite in the arsenal's sprite list
+    pub sprite: usize,
+}
+
+fn default_pellets() -> u32 {
+    1
+}
+
+/// A set of weapons together with the sprite sheet they are drawn from,
+/// loaded from a `.weapons.yml` descriptor such as `weapons/arsenal.weapons.yml`
+#[derive(Asset, TypePath, Debug)]
+pub struct Arsenal {
+    pub atlas: Handle<TextureAtlas>,
+    pub weapons: HashMap<String, Weapon>,
+}
+
+/// The on-disk format of an arsenal descriptor
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArsenalDescriptor {
+    /// Path to the sprite sheet image, relative to the descriptor
+    image: String,
+    /// Size of the sprite sheet image in pixels
+    image_size: [f32; 2],
+    /// The weapon sprites as `[x, y, width, height]` rectangles,
+    /// since the sprites in the sheet are not laid out in a grid
+    sprites: Vec<[f32; 4]>,
+    weapons: HashMap<String, Weapon>,
+}
+
+#[derive(Default)]
+pub struct ArsenalLoader;
+
+impl AssetLoader for ArsenalLoader {
+    type Asset = Arsenal;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let descriptor: ArsenalDescriptor = serde_yaml::from_slice(&bytes)?;
+
+            if !descriptor.weapons.contains_key(DEFAULT_WEAPON) {
+                anyhow::bail!("Arsenal is missing the default weapon {DEFAULT_WEAPON}");
+            }
+            for (name, weapon) in &descriptor.weapons {
+                if weapon.sprite >= descriptor.sprites.len() {
+                    anyhow::bail!("Weapon {name} uses missing sprite {}", weapon.sprite);
+                }
+                if !is_positive(weapon.fire_rate) {
+                    anyhow::bail!("Weapon {name} needs a positive fire rate");
+                }
+                if weapon.pellets == 0 {
+                    anyhow::bail!("Weapon {name} needs to fire at least one pellet");
+                }
+                if !is_non_negative(weapon.reload_time) {
+                    anyhow::bail!("Weapon {name} needs a reload time of zero or more seconds");
+                }
+                if !is_non_negative(weapon.lifetime) {
+                    anyhow::bail!("Weapon {name} needs a lifetime of zero or more seconds");
+                }
+            }
+
+            let image_path = match load_context.path().parent() {
+                Some(directory) => directory.join(&descriptor.image),
+                None => descriptor.image.into(),
+            };
+            let image = load_context.load(image_path);
+
+            let mut atlas = TextureAtlas::new_empty(image, Vec2::from(descriptor.image_size));
+            for [x, y, width, height] in descriptor.sprites {
+                atlas.add_texture(Rect::new(x, y, x + width, y + height));
+            }
+
+            Ok(Arsenal {
+                atlas: load_context.add_labeled_asset(String::from("atlas"), atlas),
+                weapons: descriptor.weapons,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["weapons.yml"]
+    }
+}
+
+fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.
+}
+
+fn is_non_negative(value: f32) -> bool {
+    value.is_finite() && value >= 0.
+}
+
+/// The ammunition of a player's current weapon
+#[derive(Component, Reflect, Clone, Debug)]
+pub struct Ammo {
+    /// Rounds left in the magazine
+    pub loaded: u32,
+    pub magazine_size: u32,
+    /// Spare rounds left for reloading, or `None` for unlimited
+    pub reserve: Option<u32>,
+    /// Ticking while a reload is in progress
+    pub reload_timer: Option<Timer>,
+    reload_time: f32,
+    full_reserve: Option<u32>,
+}
+
+impl Ammo {
+    /// A full magazine and reserve for the given weapon
+    pub fn new(weapon: &Weapon) -> Self {
+        Self {
+            loaded: weapon.magazine,
+            magazine_size: weapon.magazine,
+            reserve: weapon.reserve,
+            reload_timer: None,
+            reload_time: weapon.reload_time,
+            full_reserve: weapon.reserve,
+        }
+    }
+
+    /// Fills up the magazine and reserve, cancelling any reload in progress
+    pub fn refill(&mut self) {
+        self.loaded = self.magazine_size;
+        self.reserve = self.full_reserve;
+        self.reload_timer = None;
+    }
+
+    /// Takes on the magazine, reserve and reload time of a retuned weapon,
+    /// keeping as many of the rounds left as still fit
+    pub fn retune(&mut self, weapon: &Weapon) {
+        self.magazine_size = weapon.magazine;
+        self.loaded = self.loaded.min(weapon.magazine);
+        self.reserve = match (self.reserve, weapon.reserve) {
+            (Some(reserve), Some(full_reserve)) => Some(reserve.min(full_reserve)),
+            (_, full_reserve) => full_reserve,
+        };
+        self.full_reserve = weapon.reserve;
+        self.reload_time = weapon.reload_time;
+        if let Some(timer) = self.reload_timer.as_mut() {
+            timer.set_duration(Duration::from_secs_f32(weapon.reload_time));
+        }
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload_timer.is_some()
+    }
+
+    pub fn can_reload(&self) -> bool {
+        !self.is_reloading() && self.loaded < self.magazine_size && self.reserve != Some(0)
+    }
+
+    /// Starts reloading, unless the magazine is full or there is nothing to reload with
+    pub fn start_reload(&mut self) {
+        if self.can_reload() {
+            self.reload_timer = Some(Timer::from_seconds(self.reload_time, TimerMode::Once));
+        }
+    }
+
+    /// Moves rounds from the reserve into the magazine
+    fn finish_reload(&mut self) {
+        let missing = self.magazine_size - self.loaded;
+        let moved = match self.reserve.as_mut() {
+            Some(reserve) => {
+                let moved = missing.min(*reserve);
+                *reserve -= moved;
+                moved
+            }
+            None => missing,
+        };
+        self.loaded += moved;
+        self.reload_timer = None;
+    }
+}
+
+/// Sent when a player pulls the trigger on an empty magazine
+#[derive(Event, Clone, Debug)]
+pub struct EmptyClick {
+    pub player: Entity,
+}
+
+/// Send this event to put a weapon from the arsenal in a player's hand
+#[derive(Event, Clone, Debug)]
+pub struct EquipWeapon {
+    pub player: Entity,
+    /// Name of the weapon in the arsenal
+    pub weapon: String,
+}
+
+fn equip_weapons(
+    mut commands: Commands,
+    mut equip_events: EventReader<EquipWeapon>,
+    player_query: Query<&Children>,
+    arm_query: Query<&Children, With<AimingChild>>,
+    mut gun_query: Query<(&mut Gun, &mut TextureAtlasSprite)>,
+    weapon_assets: Res<WeaponAssets>,
+    arsenals: Res<Assets<Arsenal>>,
+) {
+    let Some(arsenal) = arsenals.get(&weapon_assets.arsenal) else {
+        return;
+    };
+
+    for equip in equip_events.read() {
+        let Some(weapon) = arsenal.weapons.get(&equip.weapon) else {
+            warn!("No weapon named {} in the arsenal", equip.weapon);
+            continue;
+        };
+        let Ok(children) = player_query.get(equip.player) else {
+            continue;
+        };
+        commands.entity(equip.player).insert(Ammo::new(weapon));
+
+        for arm_children in arm_query.iter_many(children) {
+            let mut guns = gun_query.iter_many_mut(arm_children);
+            while let Some((mut gun, mut sprite)) = guns.fetch_next() {
+                *gun = Gun::new(&equip.weapon, weapon);
+                sprite.index = weapon.sprite;
+            }
+        }
+    }
+}
+
+fn reload_guns(
+    mut query: Query<(&mut Ammo, &ActionState<PlayerAction>), Without<Dead>>,
+    mut empty_click_events: EventReader<EmptyClick>,
+) {
+    // Clicking on an empty magazine reloads automatically
+    for empty_click in empty_click_events.read() {
+        if let Ok((mut ammo, _)) = query.get_mut(empty_click.player) {
+            ammo.start_reload();
+        }
+    }
+
+    for (mut ammo, action_state) in &mut query {
+        if action_state.just_pressed(PlayerAction::Reload) {
+            ammo.start_reload();
+        }
+
+        let finished = match ammo.reload_timer.as_mut() {
+            Some(timer) => timer.tick(Duration::from_secs_f32(PHYSICS_STEP)).finished(),
+            None => false,
+        };
+        if finished {
+            ammo.finish_reload();
+        }
+    }
+}
+
+/// Updates the equipped weapons when the arsenal descriptor changes, so weapons can be tuned live
+fn reload_arsenal(
+    mut asset_events: EventReader<AssetEvent<Arsenal>>,
+    weapon_assets: Res<WeaponAssets>,
+    arsenals: Res<Assets<Arsenal>>,
+    mut player_query: Query<(&Children, &mut Ammo)>,
+    arm_query: Query<&Children, With<AimingChild>>,
+    mut gun_query: Query<(&mut Gun, &mut TextureAtlasSprite)>,
+) {
+    for asset_event in asset_events.read() {
+        if !asset_event.is_modified(&weapon_assets.arsenal) {
+            continue;
+        }
+        let Some(arsenal) = arsenals.get(&weapon_assets.arsenal) else {
+            continue;
+        };
+
+        for (children, mut ammo) in &mut player_query {
+            for arm_children in arm_query.iter_many(children) {
+                let mut guns = gun_query.iter_many_mut(arm_children);
+                while let Some((mut gun, mut sprite)) = guns.fetch_next() {
+                    if let Some(weapon) = arsenal.weapons.get(&gun.weapon_name) {
+                        // Rebuilt so the shot timer picks up the new fire rate
+                        *gun = Gun::new(&gun.weapon_name, weapon);
+                        sprite.index = weapon.sprite;
+                        ammo.retune(weapon);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weapon(magazine: u32, reserve: Option<u32>) -> Weapon {
+        Weapon {
+            fire_rate: 4.,
+            spread: 0.,
+            pellets: 1,
+            bullet_speed: 400.,
+            bounces: 0,
+            lifetime: 1.,
+            damage: 10.,
+            magazine,
+            reserve,
+            reload_time: 1.,
+            sprite: 0,
+        }
+    }
+
+    #[test]
+    fn reload_tops_up_the_magazine_from_the_reserve() {
+        let mut ammo = Ammo::new(&weapon(6, Some(10)));
+        ammo.loaded = 2;
+
+        ammo.start_reload();
+        assert!(ammo.is_reloading());
+        ammo.finish_reload();
+
+        assert!(!ammo.is_reloading());
+        assert_eq!(ammo.loaded, 6);
+        assert_eq!(ammo.reserve, Some(6));
+    }
+
+    #[test]
+    fn reload_is_limited_by_the_reserve() {
+        let mut ammo = Ammo::new(&weapon(6, Some(3)));
+        ammo.loaded = 1;
+
+        ammo.start_reload();
+        ammo.finish_reload();
+
+        assert_eq!(ammo.loaded, 4);
+        assert_eq!(ammo.reserve, Some(0));
+        assert!(!ammo.can_reload());
+    }
+
+    #[test]
+    fn unlimited_reserve_always_fills_the_magazine() {
+        let mut ammo = Ammo::new(&weapon(6, None));
+        ammo.loaded = 0;
+
+        ammo.start_reload();
+        ammo.finish_reload();
+
+        assert_eq!(ammo.loaded, 6);
+        assert_eq!(ammo.reserve, None);
+    }
+
+    #[test]
+    fn full_magazine_is_not_reloaded() {
+        let mut ammo = Ammo::new(&weapon(6, Some(10)));
+
+        ammo.start_reload();
+
+        assert!(!ammo.is_reloading());
+    }
+
+    #[test]
+    fn refill_cancels_reload_and_restores_the_reserve() {
+        let mut ammo = Ammo::new(&weapon(6, Some(10)));
+        ammo.loaded = 0;
+        ammo.start_reload();
+        ammo.finish_reload();
+
+        ammo.loaded = 0;
+        ammo.start_reload();
+        ammo.refill();
+
+        assert!(!ammo.is_reloading());
+        assert_eq!(ammo.loaded, 6);
+        assert_eq!(ammo.reserve, Some(10));
+    }
+
+    #[test]
+    fn retune_keeps_the_rounds_that_still_fit() {
+        let mut ammo = Ammo::new(&weapon(6, Some(10)));
+        ammo.loaded = 5;
+
+        ammo.retune(&weapon(3, Some(4)));
+
+        assert_eq!(ammo.loaded, 3);
+        assert_eq!(ammo.reserve, Some(4));
+        assert_eq!(ammo.magazine_size, 3);
+    }
+}
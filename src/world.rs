@@ -1,42 +1,53 @@
 use bevy::{
+    ecs::system::SystemParam,
     prelude::*,
+    transform::TransformSystem,
     utils::{HashMap, HashSet},
 };
-use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_ldtk::{ldtk::loaded_level::LoadedLevel, prelude::*};
 use bevy_rapier2d::prelude::*;
+use rand::seq::SliceRandom;
 
 pub struct WorldPlugin;
 
-#[derive(Resource)]
+/// The size of the currently loaded level in pixels, updated whenever a level spawns
+#[derive(Resource, Default)]
 pub struct GameWorld {
     pub width: f32,
     pub height: f32,
 }
 
-const WORLD_WIDTH: f32 = 960.;
-const WORLD_HEIGHT: f32 = 512.;
-
-impl Default for GameWorld {
-    fn default() -> Self {
-        Self {
-            width: WORLD_WIDTH,
-            height: WORLD_HEIGHT,
-        }
+impl GameWorld {
+    /// Whether a level has spawned yet, giving the world its size
+    pub fn is_sized(&self) -> bool {
+        self.width > 0. && self.height > 0.
     }
 }
 
+/// Run condition for systems that wrap or clamp against the [GameWorld] bounds
+pub fn world_is_sized(game_world: Res<GameWorld>) -> bool {
+    game_world.is_sized()
+}
+
 #[derive(Component)]
 pub struct ClampToWorld;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(GameWorld::default())
-            .insert_resource(LevelSelection::Uid(0))
+            .insert_resource(LevelSelection::index(0))
+            .init_resource::<SpawnPoints>()
+            .init_resource::<WallGrid>()
             .register_ldtk_int_cell::<WallBundle>(1)
-            .add_systems(Update, wrap_around_world)
-            // .register_ldtk_entity::<SpawnPoint>("Entities")
+            .register_ldtk_entity::<SpawnPointBundle>("Spawn")
+            .add_systems(Update, wrap_around_world.run_if(world_is_sized))
             .add_systems(Startup, load_level)
-            .add_systems(Update, spawn_wall_collision);
+            .add_systems(Update, spawn_wall_collision)
+            .add_systems(Update, update_world_size)
+            .add_systems(
+                PostUpdate,
+                collect_spawn_points.after(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -47,6 +58,92 @@ pub struct Wall;
 pub struct WallBundle {
     wall: Wall,
 }
+
+/// A location where players can (re)spawn, placed as `Spawn` entities in the LDtk level
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct SpawnPoint;
+
+#[derive(Clone, Debug, Default, Bundle, LdtkEntity)]
+pub struct SpawnPointBundle {
+    spawn_point: SpawnPoint,
+}
+
+/// World positions of every [SpawnPoint] in the loaded level
+#[derive(Resource, Clone, PartialEq, Debug, Default)]
+pub struct SpawnPoints {
+    pub positions: Vec<Vec2>,
+}
+
+impl SpawnPoints {
+    /// Pick the spawn point at `index`, wrapping around if there are fewer points than that
+    pub fn get(&self, index: usize) -> Option<Vec2> {
+        if self.positions.is_empty() {
+            None
+        } else {
+            Some(self.positions[index % self.positions.len()])
+        }
+    }
+
+    pub fn random(&self) -> Option<Vec2> {
+        self.positions.choose(&mut rand::thread_rng()).copied()
+    }
+}
+
+/// Where the wall tiles of the loaded level are, for things that need to know about walls
+/// without going through the physics engine
+#[derive(Resource, Clone, Debug, Default)]
+pub struct WallGrid {
+    /// World position of the bottom left corner of the level
+    pub origin: Vec2,
+    pub grid_size: f32,
+    pub walls: HashSet<GridCoords>,
+}
+
+impl WallGrid {
+    pub fn grid_coords(&self, position: Vec2) -> GridCoords {
+        let cell = ((position - self.origin) / self.grid_size).floor();
+        GridCoords::new(cell.x as i32, cell.y as i32)
+    }
+
+    /// The area in the world covered by the tile at `coords`
+    pub fn tile_rect(&self, coords: GridCoords) -> Rect {
+        let min = self.origin + Vec2::new(coords.x as f32, coords.y as f32) * self.grid_size;
+        Rect::from_corners(min, min + Vec2::splat(self.grid_size))
+    }
+
+    /// Whether the world `position` is inside a wall tile
+    pub fn is_wall(&self, position: Vec2) -> bool {
+        self.grid_size > 0. && self.walls.contains(&self.grid_coords(position))
+    }
+}
+
+/// The level entities in the world, together with their data from the LDtk project
+#[derive(SystemParam)]
+pub struct SpawnedLevels<'w, 's> {
+    level_query: Query<'w, 's, (Entity, &'static LevelIid, &'static Transform)>,
+    ldtk_projects: Query<'w, 's, &'static Handle<LdtkProject>>,
+    ldtk_project_assets: Res<'w, Assets<LdtkProject>>,
+}
+
+impl SpawnedLevels<'_, '_> {
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &LevelIid, &Transform)> {
+        self.level_query.iter()
+    }
+
+    /// The LDtk data of a level that has spawned
+    pub fn ldtk_level(&self, level_iid: &LevelIid) -> LoadedLevel<'_> {
+        self.ldtk_project_assets
+            .get(self.ldtk_projects.single())
+            .expect("Project should be loaded if level has spawned")
+            .as_standalone()
+            .get_loaded_level_by_iid(&level_iid.to_string())
+            .expect("Spawned level should exist in LDtk project")
+    }
+}
+
+/// Marks the merged colliders spawned by [spawn_wall_collision]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct WallCollider;
 /// Spawns heron collisions for the walls of a level
 ///
 /// You could just insert a ColliderBundle in to the WallBundle,
@@ -65,11 +162,12 @@ pub struct WallBundle {
 /// 4. spawn colliders for each rectangle
 pub fn spawn_wall_collision(
     mut commands: Commands,
-    wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
+    wall_query: Query<(&GridCoords, &Parent), With<Wall>>,
+    added_wall_query: Query<&Parent, Added<Wall>>,
+    wall_collider_query: Query<(Entity, &Parent), With<WallCollider>>,
     parent_query: Query<&Parent, Without<Wall>>,
-    level_query: Query<(Entity, &LevelIid)>,
-    ldtk_projects: Query<&Handle<LdtkProject>>,
-    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    levels: SpawnedLevels,
+    mut wall_grid: ResMut<WallGrid>,
 ) {
     /// Represents a wide wall that is 1 tile tall
     /// Used to spawn wall collisions
@@ -96,29 +194,44 @@ pub fn spawn_wall_collision(
     // 2. it lets us easily add the collision entities as children of the appropriate level entity
     let mut level_to_wall_locations: HashMap<Entity, HashSet<GridCoords>> = HashMap::new();
 
+    // Only levels that received new wall tiles need their colliders (re)built.
+    // This happens when a level first spawns, and again whenever the LDtk project
+    // is hot-reloaded and the level's IntGrid is respawned.
+    let changed_levels: HashSet<Entity> = added_wall_query
+        .iter()
+        .filter_map(|parent| parent_query.get(parent.get()).ok())
+        .map(|grandparent| grandparent.get())
+        .collect();
+
+    if changed_levels.is_empty() {
+        return;
+    }
+
     wall_query.for_each(|(&grid_coords, parent)| {
         // An intgrid tile's direct parent will be a layer entity, not the level entity
         // To get the level entity, you need the tile's grandparent.
         // This is where parent_query comes in.
         if let Ok(grandparent) = parent_query.get(parent.get()) {
-            level_to_wall_locations
-                .entry(grandparent.get())
-                .or_default()
-                .insert(grid_coords);
+            if changed_levels.contains(&grandparent.get()) {
+                level_to_wall_locations
+                    .entry(grandparent.get())
+                    .or_default()
+                    .insert(grid_coords);
+            }
         }
     });
 
+    // Remove the colliders built from the previous version of the level
+    for (collider_entity, parent) in &wall_collider_query {
+        if changed_levels.contains(&parent.get()) {
+            commands.entity(collider_entity).despawn_recursive();
+        }
+    }
+
     if !wall_query.is_empty() {
-        level_query.for_each(|(level_entity, level_iid)| {
+        for (level_entity, level_iid, level_transform) in levels.iter() {
             if let Some(level_walls) = level_to_wall_locations.get(&level_entity) {
-                let ldtk_project = ldtk_project_assets
-                    .get(ldtk_projects.single())
-                    .expect("Project should be loaded if level has spawned");
-
-                let level = ldtk_project
-                    .as_standalone()
-                    .get_loaded_level_by_iid(&level_iid.to_string())
-                    .expect("Spawned level should exist in LDtk project");
+                let level = levels.ldtk_level(level_iid);
 
                 let LayerInstance {
                     c_wid: width,
@@ -127,6 +240,12 @@ pub fn spawn_wall_collision(
                     ..
                 } = level.layer_instances()[0];
 
+                *wall_grid = WallGrid {
+                    origin: level_transform.translation.truncate(),
+                    grid_size: grid_size as f32,
+                    walls: level_walls.clone(),
+                };
+
                 // combine wall tiles into flat "plates" in each individual row
                 let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
 
@@ -190,7 +309,7 @@ pub fn spawn_wall_collision(
                     // 2. the colliders will be despawned automatically when levels unload
                     for wall_rect in wall_rects {
                         level
-                            .spawn_empty()
+                            .spawn(WallCollider)
                             .insert(Collider::cuboid(
                                 (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
                                     * grid_size as f32
@@ -212,7 +331,39 @@ pub fn spawn_wall_collision(
                     }
                 });
             }
-        });
+        }
+    }
+}
+
+/// Spawn points are read after transform propagation,
+/// since their world position depends on the level and layer they belong to
+fn collect_spawn_points(
+    query: Query<&GlobalTransform, With<SpawnPoint>>,
+    mut spawn_points: ResMut<SpawnPoints>,
+) {
+    let mut positions: Vec<Vec2> = query
+        .iter()
+        .map(|transform| transform.translation().truncate())
+        .collect();
+
+    // Keep a stable order so the resource only changes when the spawn points do
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+
+    spawn_points.set_if_neq(SpawnPoints { positions });
+}
+
+fn update_world_size(
+    mut level_events: EventReader<LevelEvent>,
+    levels: SpawnedLevels,
+    mut game_world: ResMut<GameWorld>,
+) {
+    for level_event in level_events.read() {
+        if let LevelEvent::Spawned(level_iid) = level_event {
+            let level = levels.ldtk_level(level_iid);
+
+            game_world.width = *level.px_wid() as f32;
+            game_world.height = *level.px_hei() as f32;
+        }
     }
 }
 
@@ -243,3 +394,25 @@ fn wrap_around_world(mut query: Query<(&mut Transform, &Collider)>, world: Res<G
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_spawn_point_before_the_level_spawns() {
+        assert_eq!(SpawnPoints::default().get(0), None);
+    }
+
+    #[test]
+    fn spawn_points_wrap_around() {
+        let spawn_points = SpawnPoints {
+            positions: vec![Vec2::new(16., 32.), Vec2::new(64., 32.)],
+        };
+
+        assert_eq!(spawn_points.get(0), Some(Vec2::new(16., 32.)));
+        assert_eq!(spawn_points.get(1), Some(Vec2::new(64., 32.)));
+        assert_eq!(spawn_points.get(2), Some(Vec2::new(16., 32.)));
+        assert_eq!(spawn_points.get(5), Some(Vec2::new(64., 32.)));
+    }
+}